@@ -0,0 +1,17 @@
+#include <stdio.h>
+#define MAX 10
+
+/* Block comments
+   don't nest in C. */
+static int count(const char *s) {
+    int n = 0; // characters
+    while (*s++ != '\0') {
+        n += 1;
+    }
+    return n;
+}
+
+int main(void) {
+    printf("%d\n", count("hello"));
+    return 0x0;
+}
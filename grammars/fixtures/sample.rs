@@ -0,0 +1,22 @@
+// Line comment with "quotes".
+use std::fmt::Display;
+
+/* A block comment /* nested */
+   still going */
+pub struct Point<'a, T> {
+    x: i32,
+    name: &'a str,
+    tag: T,
+}
+
+fn main() {
+    let c = '\n';
+    let b = b'x';
+    let s = r#"raw "string""#;
+    let n = 0x1f + 1_000 + 2.5e3;
+    for i in 0..10 {
+        println!("{} {}\"", i, Self::MAX);
+    }
+    let multi = "spans
+lines";
+}
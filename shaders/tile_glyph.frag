@@ -1,23 +1,16 @@
 #version 330 core
 
-#define FONT_WIDTH 128
-#define FONT_HEIGHT 64
-#define FONT_COLS 18
-#define FONT_ROWS 7
-#define FONT_CHAR_WIDTH  (FONT_WIDTH  / FONT_COLS)
-#define FONT_CHAR_HEIGHT (FONT_HEIGHT / FONT_ROWS)
-#define FONT_CHAR_WIDTH_UV  (float(FONT_CHAR_WIDTH) / float(FONT_WIDTH))
-#define FONT_CHAR_HEIGHT_UV (float(FONT_CHAR_HEIGHT) / float(FONT_HEIGHT))
-
-#define ASCII_DISPLAY_LOW 32
-#define ASCII_DISPLAY_HIGH 126
-
+// Coverage in the red channel.
 uniform sampler2D font;
+uniform vec2 char_size;
 uniform float time;
 uniform vec2 resolution;
 
 in vec2 uv;
-flat in int glyph_ch;
+// Where the glyph is in the atlas and in its cell, in pixels.
+flat in ivec2 atlas_pos;
+flat in ivec2 atlas_size;
+flat in ivec2 cell_offset;
 in vec4 glyph_fg_color;
 in vec4 glyph_bg_color;
 
@@ -33,21 +26,15 @@ vec3 hsl2rgb(vec3 c) {
 }
 
 void main() {
-    int ch = int(glyph_ch);
-    if (!(ASCII_DISPLAY_LOW <= ch && ch <= ASCII_DISPLAY_HIGH)) {
-        ch = 63;
+    // The cell goes up from the bottom, the atlas down from the top.
+    vec2 p = vec2(uv.x, 1.0 - uv.y) * char_size;
+    ivec2 texel = ivec2(floor(p)) - cell_offset;
+    float coverage = 0.0;
+    if (all(greaterThanEqual(texel, ivec2(0))) && all(lessThan(texel, atlas_size))) {
+        coverage = texelFetch(font, atlas_pos + texel, 0).r;
     }
 
-    int index = ch - 32;
-    float x = float(index % FONT_COLS) * FONT_CHAR_WIDTH_UV;
-    float y = float(index / FONT_COLS) * FONT_CHAR_HEIGHT_UV;
-    vec2 pos = vec2(x, y + FONT_CHAR_HEIGHT_UV);
-    vec2 size = vec2(FONT_CHAR_WIDTH_UV, -FONT_CHAR_HEIGHT_UV);
-    vec2 t = pos + size * uv;
-
-    vec4 tc = texture(font, t);
-
     vec2 frag_uv = gl_FragCoord.xy / resolution;
     vec4 rainbow = vec4(hsl2rgb(vec3((time + frag_uv.x + frag_uv.y), 0.5, 0.5)), 1.0);
-    outFragColor = glyph_bg_color * (1.0 - tc.x) + tc.x * glyph_fg_color * rainbow;
+    outFragColor = glyph_bg_color * (1.0 - coverage) + coverage * glyph_fg_color * rainbow;
 }
@@ -1,24 +1,23 @@
 #version 330 core
 
-#define FONT_WIDTH 128
-#define FONT_HEIGHT 64
-#define FONT_COLS 18
-#define FONT_ROWS 7
-#define FONT_CHAR_WIDTH  (FONT_WIDTH  / FONT_COLS)
-#define FONT_CHAR_HEIGHT (FONT_HEIGHT / FONT_ROWS)
-
 uniform vec2 resolution;
 uniform float scale;
+// In font pixels.
+uniform vec2 char_size;
 uniform vec2 camera;
 uniform float time;
 
 layout(location = 0) in ivec2 tile;
-layout(location = 1) in int ch;
-layout(location = 2) in vec4 fg_color;
-layout(location = 3) in vec4 bg_color;
+layout(location = 1) in ivec2 glyph_pos;
+layout(location = 2) in ivec2 glyph_size;
+layout(location = 3) in ivec2 glyph_offset;
+layout(location = 4) in vec4 fg_color;
+layout(location = 5) in vec4 bg_color;
 
 out vec2 uv;
-flat out int glyph_ch;
+flat out ivec2 atlas_pos;
+flat out ivec2 atlas_size;
+flat out ivec2 cell_offset;
 out vec4 glyph_fg_color;
 out vec4 glyph_bg_color;
 
@@ -29,14 +28,15 @@ vec2 project_point(vec2 point)
 
 void main() {
     uv = vec2(float(gl_VertexID & 1), float((gl_VertexID >> 1) & 1));
-    vec2 char_size = vec2(float(FONT_CHAR_WIDTH), float(FONT_CHAR_HEIGHT));
     // float amplitude = 5.0;
     // vec2 shaking = vec2(cos(time + tile.x), sin(time + tile.y)) * amplitude;
     vec2 shaking = vec2(0.0);
     vec2 pos = tile * char_size * scale + shaking;
 
     gl_Position = vec4(project_point(uv * char_size * scale + pos), 0.0, 1.0);
-    glyph_ch = ch;
+    atlas_pos = glyph_pos;
+    atlas_size = glyph_size;
+    cell_offset = glyph_offset;
 
     glyph_fg_color = fg_color;
     glyph_bg_color = bg_color;
@@ -1,144 +1,902 @@
-use crate::v2s;
-use crate::vector::Vector2;
+use unicode_segmentation::UnicodeSegmentation;
 
-#[derive(Default)]
-pub struct Line {
-    pub chars: String,
+use crate::history::{Edit, History};
+use crate::regex::Text;
+use crate::swap;
+use crate::swap::Recovery;
+use crate::watch::Fingerprint;
+use crate::watch::Watcher;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    pub y: usize,
+    // Byte offset inside the line, always on a grapheme boundary.
+    pub x: usize,
+    // Display column, which counts grapheme clusters instead of bytes.
+    pub col: usize,
+    // Column that moving up and down tries to go back to.
+    goal_col: usize,
+}
+
+// Width in cells of `s` as the tile renderer lays it out: one per grapheme cluster.
+pub fn display_width(s: &str) -> usize {
+    s.graphemes(true).count()
+}
+
+// Byte offset of the grapheme at `col`, or `s.len()` if `s` is narrower than that.
+pub fn col_to_byte(s: &str, col: usize) -> usize {
+    s.grapheme_indices(true)
+        .nth(col)
+        .map_or(s.len(), |(i, _)| i)
 }
 
-impl Line {
-    fn insert(&mut self, col: usize, text: &str) {
-        self.chars.insert_str(col, text);
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+        }
     }
-    fn remove(&mut self, col: usize) {
-        if !self.chars.is_empty() {
-            self.chars.remove(col);
+}
+
+const BOM: &str = "\u{feff}";
+
+// File contents split into the text that goes in the buffer and how it's encoded.
+struct Decoded<'a> {
+    text: Cow<'a, str>,
+    line_ending: LineEnding,
+    trailing_newline: bool,
+    bom: bool,
+}
+
+impl<'a> Decoded<'a> {
+    fn new(contents: &'a str) -> Self {
+        let bom = contents.starts_with(BOM);
+        let contents = contents.strip_prefix(BOM).unwrap_or(contents);
+        // Only CRLF when every line ends that way, otherwise a stray '\r'
+        // is kept in the text so it gets written back exactly as it was.
+        let newlines = contents.matches('\n').count();
+        let crlf = newlines > 0 && contents.matches("\r\n").count() == newlines;
+        let (text, line_ending) = if crlf {
+            (Cow::Owned(contents.replace("\r\n", "\n")), LineEnding::CrLf)
+        } else {
+            (Cow::Borrowed(contents), LineEnding::Lf)
+        };
+        let trailing_newline = text.ends_with('\n');
+        let text = match text {
+            Cow::Borrowed(text) => Cow::Borrowed(text.strip_suffix('\n').unwrap_or(text)),
+            Cow::Owned(mut text) => {
+                if trailing_newline {
+                    text.pop();
+                }
+                Cow::Owned(text)
+            }
+        };
+        Self {
+            text,
+            line_ending,
+            trailing_newline,
+            bom,
         }
     }
 }
 
-#[derive(Default)]
+// How far back `Buffer::follow` can catch up with.
+const JOURNAL_CAP: usize = 4096;
+
+// A change to the text, only in terms of offsets.
+#[derive(Debug, Clone, Copy)]
+enum Change {
+    Insert { at: usize, len: usize },
+    Remove { at: usize, len: usize },
+}
+
+// Where a view of the buffer that isn't the one being edited was left.
+// `Buffer::follow` brings it up to date with the edits made since.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Anchor {
+    cursor: usize,
+    mark: Option<usize>,
+    goal_col: usize,
+    revision: usize,
+}
+
 pub struct Buffer {
     filepath: Option<PathBuf>,
-    pub lines: Vec<Line>,
-    pub cursor: Vector2<usize>,
+    // Only "\n" lives in `text`, these say how the file is written back.
+    pub line_ending: LineEnding,
+    pub trailing_newline: bool,
+    pub bom: bool,
+    text: Gap,
+    // Byte offset where each line starts inside `text`.
+    // There's always at least one line, starting at 0.
+    lines: Vec<usize>,
+    pub cursor: Cursor,
+    // Offset where the selection started, the cursor being its other end.
+    mark: Option<usize>,
+    history: History,
+    // What was last written to disk, to tell whether there are unsaved changes.
+    saved_state: usize,
+    saved_line_ending: LineEnding,
+    // State last journaled to the swap file, if there's one.
+    swapped_state: Option<usize>,
+    // Swap file found on load, waiting for `recover` or `discard_recovery`.
+    pub recovery: Option<Recovery>,
+    // The file as it was last loaded or saved, None if it didn't exist.
+    disk: Option<Fingerprint>,
+    // Change on disk already reported by `check_disk`, so it's only reported once.
+    seen: Option<Fingerprint>,
+    watcher: Option<Watcher>,
+    // The latest changes to the text, the first being revision `journal_start`.
+    journal: Vec<Change>,
+    journal_start: usize,
 }
 
+#[derive(Debug, PartialEq, Eq)]
+pub enum DiskChange {
+    Reloaded,
+    // Someone else changed the file while there were unsaved changes.
+    Conflict,
+}
+
+use std::borrow::Cow;
+use std::fs;
 use std::fs::File;
 use std::io;
-use std::io::BufRead;
+use std::io::Read;
 use std::io::Write;
 use std::iter::Chain;
+use std::ops::Range;
+use std::path::Path;
 use std::path::PathBuf;
-use std::str::Chars;
-use std::str::FromStr;
 use std::str::Lines;
 
+const BUFFER_INITIAL_CAP: usize = 1024;
+
+impl Default for Buffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Buffer {
     pub fn new() -> Self {
         Self {
             filepath: None,
-            lines: vec![Line::default()],
-            cursor: v2s!(0),
+            line_ending: LineEnding::Lf,
+            trailing_newline: true,
+            bom: false,
+            text: Gap::new(BUFFER_INITIAL_CAP),
+            lines: vec![0],
+            cursor: Cursor::default(),
+            mark: None,
+            history: History::default(),
+            saved_state: 0,
+            saved_line_ending: LineEnding::Lf,
+            swapped_state: None,
+            recovery: None,
+            disk: None,
+            seen: None,
+            watcher: None,
+            journal: Vec::new(),
+            journal_start: 0,
         }
     }
     pub fn from_filepath(filepath: String) -> std::io::Result<Self> {
         let filepath = PathBuf::from(filepath);
-        let file = match File::open(&filepath) {
+        let mut file = match File::open(&filepath) {
             Ok(file) => file,
-            Err(_) => {
-                // it's alright if file doesn't exist
+            // it's alright if file doesn't exist, though it may have been
+            // edited before without ever getting saved
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
                 return Ok(Self {
+                    recovery: swap::find_leftover(&filepath, ""),
                     filepath: Some(filepath),
                     ..Self::new()
                 });
             }
+            Err(err) => return Err(err),
         };
-        let mut buffer = Self::default();
-        for line in io::BufReader::new(file).lines() {
-            let mut chars = line?;
-            if chars.ends_with('\n') {
-                chars.pop();
-            }
-            buffer.lines.push(Line { chars });
-        }
-        if buffer.lines.is_empty() {
-            buffer.lines.push(Line::default());
-        }
-        buffer.filepath = Some(filepath);
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        let mtime = file
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        let mut buffer = Self {
+            recovery: swap::find_leftover(&filepath, &contents),
+            disk: Some(Fingerprint::new(contents.as_bytes(), mtime)),
+            filepath: Some(filepath),
+            text: Gap::new(contents.len() + BUFFER_INITIAL_CAP),
+            ..Self::new()
+        };
+        let decoded = Decoded::new(&contents);
+        buffer.set_format(&decoded);
+        buffer.saved_line_ending = buffer.line_ending;
+        buffer.insert_raw(0, &decoded.text);
         Ok(buffer)
     }
-    pub fn save(&self) -> std::io::Result<()> {
-        let mut file = std::fs::File::options()
+    fn set_format(&mut self, decoded: &Decoded) {
+        self.bom = decoded.bom;
+        self.line_ending = decoded.line_ending;
+        self.trailing_newline = decoded.trailing_newline;
+    }
+    pub fn filepath(&self) -> Option<&Path> {
+        self.filepath.as_deref()
+    }
+    pub fn save(&mut self) -> std::io::Result<()> {
+        let Some(filepath) = self.filepath.as_deref() else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "buffer has no file path, save it as something first",
+            ));
+        };
+        // Replace what a symlink points to, not the symlink itself.
+        let filepath = fs::canonicalize(filepath).unwrap_or_else(|_| filepath.to_owned());
+        self.write_atomically(&filepath, &filepath)?;
+        // Typing after a save has to be undoable back to what's on disk.
+        self.history.seal();
+        self.saved_state = self.history.state();
+        self.saved_line_ending = self.line_ending;
+        self.disk = Some(Fingerprint::of(&filepath, None)?);
+        self.seen = None;
+        self.remove_swap()
+    }
+    // Writes everything to a temporary file next to `path` and renames it over
+    // `path`, so a crash halfway through can't leave it half written.
+    fn write_atomically(&self, path: &Path, permissions_from: &Path) -> std::io::Result<()> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let tmp_path = dir.join(format!(".{}.red-tmp-{}", name, std::process::id()));
+
+        let result = self.write_file(&tmp_path, permissions_from).and_then(|_| {
+            fs::rename(&tmp_path, path)?;
+            // The rename itself only survives a crash once the directory is synced,
+            // which only works where directories can be opened like files.
+            #[cfg(unix)]
+            File::open(dir)?.sync_all()?;
+            Ok(())
+        });
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+        result
+    }
+    pub fn is_modified(&self) -> bool {
+        self.history.state() != self.saved_state || self.line_ending != self.saved_line_ending
+    }
+    fn write_file(&self, path: &Path, permissions_from: &Path) -> std::io::Result<()> {
+        let file = File::options()
             .create(true)
             .write(true)
             .truncate(true)
-            .open(
-                self.filepath
-                    .as_ref()
-                    .unwrap_or(&PathBuf::from_str("output").unwrap()),
-            )?;
-        for line in &self.lines {
-            file.write_all(&line.chars.as_bytes())?;
-            file.write(&[b'\n'])?;
+            .open(path)?;
+        if let Ok(metadata) = fs::metadata(permissions_from) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        let mut writer = io::BufWriter::new(file);
+        self.write_to(&mut writer)?;
+        writer.into_inner()?.sync_all()
+    }
+    // Only moves on to `filepath` once it's saved there, a failed save leaves
+    // the buffer, and the journal of its old path, as they were.
+    pub fn save_as(&mut self, filepath: PathBuf) -> std::io::Result<()> {
+        let old = self.filepath.replace(filepath);
+        if let Err(err) = self.save() {
+            self.filepath = old;
+            return Err(err);
+        }
+        // The journal of the old path would otherwise be left behind.
+        match old {
+            Some(old) if self.filepath.as_ref() != Some(&old) && self.recovery.is_none() => {
+                remove_if_exists(&swap::swap_path(&old))
+            }
+            _ => Ok(()),
+        }
+    }
+    // Whether the file is no longer what was last loaded or saved.
+    pub fn changed_on_disk(&self) -> bool {
+        let Some(filepath) = self.filepath.as_deref() else {
+            return false;
+        };
+        match (&self.disk, Fingerprint::of(filepath, self.disk.as_ref())) {
+            (Some(disk), Ok(current)) => !current.same_contents(disk),
+            (None, Ok(_)) => true,
+            // Saving just brings it back.
+            (_, Err(_)) => false,
+        }
+    }
+    // Cheap enough to call every frame, only looks at the file when the watcher says so.
+    pub fn poll_disk(&mut self) -> std::io::Result<Option<DiskChange>> {
+        let Some(filepath) = self.filepath.as_deref() else {
+            return Ok(None);
+        };
+        // Nothing was watching before, so whatever happened until now isn't known.
+        if self.watcher.as_ref().map(|watcher| watcher.path()) != Some(filepath) {
+            self.watcher = Some(Watcher::new(filepath));
+            return self.check_disk();
+        }
+        if !self.watcher.as_mut().is_some_and(|watcher| watcher.poll()) {
+            return Ok(None);
+        }
+        self.check_disk()
+    }
+    // Reloads the file if it changed and there's nothing to lose by doing so.
+    pub fn check_disk(&mut self) -> std::io::Result<Option<DiskChange>> {
+        if !self.changed_on_disk() {
+            return Ok(None);
+        }
+        if !self.is_modified() {
+            self.reload()?;
+            return Ok(Some(DiskChange::Reloaded));
         }
+        let Some(filepath) = self.filepath.as_deref() else {
+            return Ok(None);
+        };
+        let current = Fingerprint::of(filepath, self.seen.as_ref())?;
+        if self
+            .seen
+            .as_ref()
+            .is_some_and(|seen| seen.same_contents(&current))
+        {
+            return Ok(None);
+        }
+        self.seen = Some(current);
+        Ok(Some(DiskChange::Conflict))
+    }
+    // Takes in what's on disk, as a single undo step so unsaved changes aren't lost for good.
+    pub fn reload(&mut self) -> std::io::Result<()> {
+        let Some(filepath) = self.filepath.clone() else {
+            return Ok(());
+        };
+        let mut file = File::open(&filepath)?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        let mtime = file
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok();
+
+        let decoded = Decoded::new(&contents);
+        self.set_format(&decoded);
+        if self.text_range(0..self.len()) != decoded.text {
+            let cursor = self.cursor_offset();
+            self.replace_range(0..self.len(), &decoded.text);
+            self.set_cursor_offset(cursor);
+        }
+        self.history.seal();
+        self.saved_state = self.history.state();
+        self.saved_line_ending = self.line_ending;
+        self.disk = Some(Fingerprint::new(contents.as_bytes(), mtime));
+        self.seen = None;
         Ok(())
     }
+    // Journals unsaved changes to the swap file, meant to be called every now and then.
+    pub fn write_swap(&mut self) -> std::io::Result<()> {
+        let Some(filepath) = self.filepath.as_deref() else {
+            return Ok(());
+        };
+        // Don't clobber a leftover swap file before deciding what to do with it.
+        if self.recovery.is_some() {
+            return Ok(());
+        }
+        if !self.is_modified() {
+            return self.remove_swap();
+        }
+        let state = self.history.state();
+        if self.swapped_state != Some(state) {
+            self.write_atomically(&swap::swap_path(filepath), filepath)?;
+            self.swapped_state = Some(state);
+        }
+        Ok(())
+    }
+    pub fn remove_swap(&mut self) -> std::io::Result<()> {
+        let Some(filepath) = self.filepath.as_deref() else {
+            return Ok(());
+        };
+        if self.recovery.is_some() {
+            return Ok(());
+        }
+        self.swapped_state = None;
+        remove_if_exists(&swap::swap_path(filepath))
+    }
+    // Replaces the text with what the leftover swap file had, as a single undo step.
+    pub fn recover(&mut self) {
+        let Some(recovery) = self.recovery.take() else {
+            return;
+        };
+        let decoded = Decoded::new(&recovery.contents);
+        self.set_format(&decoded);
+        let cursor = self.cursor_offset();
+        self.replace_range(0..self.len(), &decoded.text);
+        self.set_cursor_offset(cursor);
+    }
+    pub fn discard_recovery(&mut self) -> std::io::Result<()> {
+        match self.recovery.take() {
+            Some(recovery) => fs::remove_file(recovery.swap_path),
+            None => Ok(()),
+        }
+    }
+    pub fn write_to(&self, out: &mut impl Write) -> std::io::Result<()> {
+        if self.bom {
+            out.write_all(BOM.as_bytes())?;
+        }
+        let ending = self.line_ending.as_str().as_bytes();
+        let (a, b) = self.text.to_str();
+        for part in [a, b] {
+            let mut lines = part.split('\n');
+            if let Some(first) = lines.next() {
+                out.write_all(first.as_bytes())?;
+            }
+            for line in lines {
+                out.write_all(ending)?;
+                out.write_all(line.as_bytes())?;
+            }
+        }
+        if self.trailing_newline {
+            out.write_all(ending)?;
+        }
+        out.flush()
+    }
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        self.line_ending = line_ending;
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+    pub fn line_start(&self, y: usize) -> usize {
+        self.lines[y]
+    }
+    // Offset of the line's '\n', or the end of the text for the last line.
+    pub fn line_end(&self, y: usize) -> usize {
+        match self.lines.get(y + 1) {
+            Some(next) => next - 1,
+            None => self.text.len(),
+        }
+    }
+    // Line that `offset` falls into.
+    pub fn line_of(&self, offset: usize) -> usize {
+        self.lines.partition_point(|&start| start <= offset) - 1
+    }
+    pub fn line_len(&self, y: usize) -> usize {
+        self.line_end(y) - self.line_start(y)
+    }
+    pub fn line(&self, y: usize) -> Cow<'_, str> {
+        self.text.slice(self.line_start(y)..self.line_end(y))
+    }
+    pub fn len(&self) -> usize {
+        self.text.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.text.is_empty()
+    }
+    pub fn cursor_offset(&self) -> usize {
+        self.line_start(self.cursor.y) + self.cursor.x
+    }
+    pub fn set_cursor_offset(&mut self, offset: usize) {
+        self.history.seal();
+        self.place_cursor(offset);
+    }
+    fn place_cursor(&mut self, offset: usize) {
+        self.cursor = self.cursor_at(offset);
+    }
+    pub fn cursor_at(&self, offset: usize) -> Cursor {
+        let offset = std::cmp::min(offset, self.text.len());
+        let y = self.line_of(offset);
+        let x = offset - self.lines[y];
+        let col = display_width(&self.line(y)[..x]);
+        Cursor {
+            y,
+            x,
+            col,
+            goal_col: col,
+        }
+    }
+
+    pub fn revision(&self) -> usize {
+        self.journal_start + self.journal.len()
+    }
+    fn record(&mut self, change: Change) {
+        if self.journal.len() == JOURNAL_CAP {
+            self.journal.drain(..JOURNAL_CAP / 2);
+            self.journal_start += JOURNAL_CAP / 2;
+        }
+        self.journal.push(change);
+    }
+    // Where `offset` at `revision` ended up after the changes made since.
+    // Text inserted right at it goes after it.
+    pub fn map_offset(&self, offset: usize, revision: usize) -> usize {
+        // Too far behind to tell, the best that can be done is to stay in bounds.
+        if revision < self.journal_start {
+            return offset.min(self.len());
+        }
+        let changes = self
+            .journal
+            .get(revision - self.journal_start..)
+            .unwrap_or(&[]);
+        changes.iter().fold(offset, |offset, change| match *change {
+            Change::Insert { at, len } if offset > at => offset + len,
+            Change::Remove { at, len } if offset >= at + len => offset - len,
+            Change::Remove { at, .. } if offset > at => at,
+            _ => offset,
+        })
+    }
+    // The part of the text the changes made since `revision` touched, all of it
+    // when that's too far behind to tell, or None if nothing changed.
+    pub fn changed_since(&self, revision: usize) -> Option<Range<usize>> {
+        if revision < self.journal_start {
+            return Some(0..self.len());
+        }
+        let changes = self
+            .journal
+            .get(revision - self.journal_start..)
+            .unwrap_or(&[]);
+        changes.iter().fold(None, |changed, change| {
+            let (at, end) = match *change {
+                Change::Insert { at, len } => (at, at + len),
+                Change::Remove { at, .. } => (at, at),
+            };
+            let Some(changed) = changed else {
+                return Some(at..end);
+            };
+            // Where what was changed before ends now.
+            let changed_end = match *change {
+                Change::Insert { at, len } if changed.end >= at => changed.end + len,
+                Change::Remove { at, len } if changed.end >= at + len => changed.end - len,
+                Change::Remove { at, .. } if changed.end > at => at,
+                _ => changed.end,
+            };
+            Some(changed.start.min(at)..changed_end.max(end))
+        })
+    }
+    pub fn anchor(&self) -> Anchor {
+        Anchor {
+            cursor: self.cursor_offset(),
+            mark: self.mark,
+            goal_col: self.cursor.goal_col,
+            revision: self.revision(),
+        }
+    }
+    pub fn follow(&self, anchor: Anchor) -> Anchor {
+        Anchor {
+            cursor: self.map_offset(anchor.cursor, anchor.revision),
+            mark: anchor
+                .mark
+                .map(|mark| self.map_offset(mark, anchor.revision)),
+            goal_col: anchor.goal_col,
+            revision: self.revision(),
+        }
+    }
+    pub fn anchor_cursor(&self, anchor: Anchor) -> Cursor {
+        self.cursor_at(self.follow(anchor).cursor)
+    }
+    // Makes the cursor and selection the ones of the view `anchor` was taken from.
+    pub fn restore(&mut self, anchor: Anchor) {
+        let anchor = self.follow(anchor);
+        self.set_cursor_offset(anchor.cursor);
+        self.cursor.goal_col = anchor.goal_col;
+        self.mark = anchor.mark;
+    }
+
+    // Every edit goes through `insert` and `remove` so it ends up in the history.
+    // Public operations then `commit` them as a single undo step.
+    fn insert(&mut self, at: usize, s: &str) {
+        if s.is_empty() {
+            return;
+        }
+        self.insert_raw(at, s);
+        self.history.push(Edit::Insert {
+            at,
+            text: s.to_owned(),
+        });
+    }
+    fn remove(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        let text = self.text.slice(range.clone()).into_owned();
+        self.remove_raw(range.clone());
+        self.history.push(Edit::Delete {
+            at: range.start,
+            text,
+        });
+    }
+    fn commit(&mut self, cursor_before: Cursor, typed: bool) {
+        self.mark = None;
+        self.history.commit(cursor_before, self.cursor, typed);
+    }
+
+    // The only ones that know how to keep `lines` in sync with `text`.
+    fn insert_raw(&mut self, at: usize, s: &str) {
+        if s.is_empty() {
+            return;
+        }
+        self.text.insert_str(at, s);
+        self.record(Change::Insert { at, len: s.len() });
+        let first_after = self.lines.partition_point(|&start| start <= at);
+        for start in &mut self.lines[first_after..] {
+            *start += s.len();
+        }
+        let new_lines = s.match_indices('\n').map(|(i, _)| at + i + 1);
+        self.lines.splice(first_after..first_after, new_lines);
+    }
+    fn remove_raw(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        self.text.remove(range.start, range.len());
+        self.record(Change::Remove {
+            at: range.start,
+            len: range.len(),
+        });
+        let first_removed = self.lines.partition_point(|&start| start <= range.start);
+        let first_kept = self.lines.partition_point(|&start| start <= range.end);
+        self.lines.drain(first_removed..first_kept);
+        for start in &mut self.lines[first_removed..] {
+            *start -= range.len();
+        }
+    }
+
+    fn apply(&mut self, edit: &Edit) {
+        match edit {
+            Edit::Insert { at, text } => self.insert_raw(*at, text),
+            Edit::Delete { at, text } => self.remove_raw(*at..*at + text.len()),
+        }
+    }
+
+    pub fn undo(&mut self) {
+        self.mark = None;
+        if let Some(step) = self.history.undo() {
+            for edit in step.edits.iter().rev() {
+                self.apply(&edit.inverse());
+            }
+            self.cursor = step.cursor_before;
+        }
+    }
+    pub fn redo(&mut self) {
+        self.mark = None;
+        if let Some(step) = self.history.redo() {
+            for edit in &step.edits {
+                self.apply(edit);
+            }
+            self.cursor = step.cursor_after;
+        }
+    }
+
+    fn prev_grapheme_boundary(&self) -> usize {
+        let line = self.line(self.cursor.y);
+        let prev = line[..self.cursor.x]
+            .grapheme_indices(true)
+            .next_back()
+            .map_or(0, |(i, _)| i);
+        self.line_start(self.cursor.y) + prev
+    }
+    fn next_grapheme_boundary(&self) -> usize {
+        let line = self.line(self.cursor.y);
+        let next = line[self.cursor.x..]
+            .graphemes(true)
+            .next()
+            .map_or(0, str::len);
+        self.cursor_offset() + next
+    }
+
+    pub fn text(&self) -> Text<'_> {
+        let (front, back) = self.text.to_str();
+        Text::new(front, back)
+    }
+    pub fn text_range(&self, range: Range<usize>) -> Cow<'_, str> {
+        self.text.slice(range)
+    }
+
+    // Anchors the selection at the cursor, unless one is already going on.
+    pub fn start_selection(&mut self) {
+        if self.mark.is_none() {
+            self.mark = Some(self.cursor_offset());
+        }
+    }
+    // Selects `range`, leaving the cursor at its end.
+    pub fn select(&mut self, range: Range<usize>) {
+        self.set_cursor_offset(range.end);
+        self.mark = Some(range.start);
+    }
+    pub fn clear_selection(&mut self) {
+        self.mark = None;
+    }
+    pub fn selection(&self) -> Option<Range<usize>> {
+        let mark = self.mark?;
+        let cursor = self.cursor_offset();
+        let range = std::cmp::min(mark, cursor)..std::cmp::max(mark, cursor);
+        (!range.is_empty()).then_some(range)
+    }
+    pub fn selected_text(&self) -> Option<String> {
+        self.selection()
+            .map(|range| self.text_range(range).into_owned())
+    }
+    // Part of the selection inside line `y`, relative to the line start.
+    pub fn selection_on_line(&self, y: usize) -> Option<Range<usize>> {
+        let selection = self.selection()?;
+        let (start, end) = (self.line_start(y), self.line_end(y));
+        if selection.end <= start || selection.start > end {
+            return None;
+        }
+        let range =
+            selection.start.saturating_sub(start)..std::cmp::min(selection.end, end) - start;
+        (!range.is_empty()).then_some(range)
+    }
+    pub fn delete_range(&mut self, range: Range<usize>) {
+        self.replace_range(range, "");
+    }
+    pub fn replace_range(&mut self, range: Range<usize>, text: &str) {
+        let before = self.cursor;
+        self.remove(range.clone());
+        self.insert(range.start, text);
+        self.place_cursor(range.start + text.len());
+        self.commit(before, false);
+    }
+
+    // Replaces many non-overlapping ranges, sorted by offset, as a single undo step.
+    pub fn replace_ranges(&mut self, replacements: &[(Range<usize>, String)]) {
+        if replacements.is_empty() {
+            return;
+        }
+        let before = self.cursor;
+        let mut cursor = self.cursor_offset();
+        // Going backwards keeps the offsets of the ranges still to be replaced valid.
+        for (range, text) in replacements.iter().rev() {
+            self.remove(range.clone());
+            self.insert(range.start, text);
+            if cursor >= range.end {
+                cursor = cursor - range.len() + text.len();
+            } else if cursor > range.start {
+                cursor = range.start;
+            }
+        }
+        self.place_cursor(cursor);
+        self.commit(before, false);
+    }
+
+    // Whole line `y`, along with the line break that separates it from the rest.
+    pub fn line_range(&self, y: usize) -> Range<usize> {
+        let mut range = self.line_start(y)..self.line_end(y);
+        if y + 1 < self.line_count() {
+            range.end += 1;
+        } else if y > 0 {
+            range.start -= 1;
+        }
+        range
+    }
+    // Copies the selection, or the current line if there's nothing selected.
+    pub fn copy(&self) -> String {
+        match self.selected_text() {
+            Some(text) => text,
+            None => format!("{}\n", self.line(self.cursor.y)),
+        }
+    }
+    pub fn cut(&mut self) -> String {
+        let text = self.copy();
+        let range = self
+            .selection()
+            .unwrap_or_else(|| self.line_range(self.cursor.y));
+        self.delete_range(range);
+        text
+    }
+    pub fn paste(&mut self, text: &str) {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        let offset = self.cursor_offset();
+        let range = self.selection().unwrap_or(offset..offset);
+        self.replace_range(range, &text);
+    }
+
     pub fn backspace(&mut self) {
+        if let Some(selection) = self.selection() {
+            return self.delete_range(selection);
+        }
+        let before = self.cursor;
+        let offset = self.cursor_offset();
         if self.cursor.x == 0 && self.cursor.y > 0 {
-            let right_side = self.lines.remove(self.cursor.y);
-            self.cursor.y -= 1;
-            self.cursor.x = self.lines[self.cursor.y].chars.len();
-            self.lines[self.cursor.y].chars.push_str(&right_side.chars);
+            self.remove(offset - 1..offset);
+            self.place_cursor(offset - 1);
         } else if self.cursor.x > 0 {
-            self.cursor.x -= 1;
-            self.lines[self.cursor.y].remove(self.cursor.x);
+            let prev = self.prev_grapheme_boundary();
+            self.remove(prev..offset);
+            self.place_cursor(prev);
         }
+        self.commit(before, false);
     }
     pub fn delete(&mut self) {
-        if self.cursor.x == self.lines[self.cursor.y].chars.len()
-            && self.lines.len() > self.cursor.y + 1
-        {
-            let right_side = self.lines.remove(self.cursor.y + 1);
-            self.lines[self.cursor.y].chars.push_str(&right_side.chars);
-        } else if self.cursor.x < self.lines[self.cursor.y].chars.len() {
-            self.lines[self.cursor.y].remove(self.cursor.x);
+        if let Some(selection) = self.selection() {
+            return self.delete_range(selection);
+        }
+        let before = self.cursor;
+        let offset = self.cursor_offset();
+        if self.cursor.x == self.line_len(self.cursor.y) && self.cursor.y + 1 < self.line_count() {
+            self.remove(offset..offset + 1);
+        } else if self.cursor.x < self.line_len(self.cursor.y) {
+            let next = self.next_grapheme_boundary();
+            self.remove(offset..next);
         }
+        self.commit(before, false);
     }
     pub fn move_left(&mut self) {
         if self.cursor.x > 0 {
-            self.cursor.x -= 1
+            let prev = self.prev_grapheme_boundary();
+            self.set_cursor_offset(prev);
         }
     }
     pub fn move_right(&mut self) {
-        if self.cursor.x < self.lines[self.cursor.y].chars.len() {
-            self.cursor.x += 1;
+        if self.cursor.x < self.line_len(self.cursor.y) {
+            let next = self.next_grapheme_boundary();
+            self.set_cursor_offset(next);
         }
     }
     pub fn move_up(&mut self) {
         if self.cursor.y > 0 {
-            self.cursor.x = std::cmp::min(self.lines[self.cursor.y - 1].chars.len(), self.cursor.x);
-            self.cursor.y -= 1;
+            self.move_to_line(self.cursor.y - 1);
         }
     }
     pub fn move_down(&mut self) {
-        if self.cursor.y != self.lines.len() - 1 {
-            self.cursor.x = std::cmp::min(self.lines[self.cursor.y + 1].chars.len(), self.cursor.x);
-            self.cursor.y += 1;
+        if self.cursor.y + 1 < self.line_count() {
+            self.move_to_line(self.cursor.y + 1);
         }
     }
+    fn move_to_line(&mut self, y: usize) {
+        self.history.seal();
+        let goal_col = self.cursor.goal_col;
+        let line = self.line(y);
+        let (x, col) = (
+            col_to_byte(&line, goal_col),
+            std::cmp::min(goal_col, display_width(&line)),
+        );
+        self.cursor = Cursor {
+            y,
+            x,
+            col,
+            goal_col,
+        };
+    }
     pub fn newline(&mut self) {
-        let new_line = self.lines[self.cursor.y].chars.split_off(self.cursor.x);
-        self.cursor.x = 0;
-        self.cursor.y += 1;
-        self.lines.insert(self.cursor.y, Line { chars: new_line });
+        self.insert_text("\n");
     }
     pub fn insert_text(&mut self, text: &str) {
-        self.lines[self.cursor.y].insert(self.cursor.x, text);
-        self.cursor.x += text.len();
+        if let Some(selection) = self.selection() {
+            return self.replace_range(selection, text);
+        }
+        let before = self.cursor;
+        let offset = self.cursor_offset();
+        self.insert(offset, text);
+        self.place_cursor(offset + text.len());
+        self.commit(before, !text.contains('\n'));
+    }
+    pub fn grapheme_at_cursor(&self) -> Option<String> {
+        self.grapheme_at(&self.cursor)
     }
-    pub fn char_at_cursor(&self) -> Option<char> {
-        self.lines[self.cursor.y].chars.chars().nth(self.cursor.x)
+    pub fn grapheme_at(&self, cursor: &Cursor) -> Option<String> {
+        let line = self.line(cursor.y);
+        line[cursor.x..].graphemes(true).next().map(str::to_owned)
+    }
+}
+
+fn remove_if_exists(path: &Path) -> std::io::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
     }
 }
 
@@ -190,7 +948,7 @@ impl Gap {
         let new_cap = 2 * (n_required + self.buf.capacity() - gap_len);
         self.buf.reserve(new_cap - self.buf.capacity());
         self.buf
-            .extend(std::iter::repeat(0).take(self.buf.capacity() - self.buf.len()));
+            .extend(std::iter::repeat_n(0, self.buf.capacity() - self.buf.len()));
         self.end = self.buf.capacity();
     }
 
@@ -217,6 +975,31 @@ impl Gap {
         self.start += s_bytes.len();
     }
 
+    pub fn remove(&mut self, at: usize, n: usize) {
+        self.shift_gap_to(at);
+        self.end = std::cmp::min(self.end + n, self.buf.len());
+    }
+
+    pub fn len(&self) -> usize {
+        self.buf.len() - (self.end - self.start)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Only allocates when `range` crosses the gap.
+    pub fn slice(&self, range: Range<usize>) -> Cow<'_, str> {
+        let (a, b) = self.to_str();
+        if range.end <= a.len() {
+            Cow::Borrowed(&a[range])
+        } else if range.start >= a.len() {
+            Cow::Borrowed(&b[range.start - a.len()..range.end - a.len()])
+        } else {
+            Cow::Owned([&a[range.start..], &b[..range.end - a.len()]].concat())
+        }
+    }
+
     // Safe because we only insert `char` and `&str`
     // which are both guaranteed to be utf8.
     pub fn to_str(&self) -> (&str, &str) {
@@ -236,6 +1019,7 @@ impl Gap {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_dir::TestDir;
 
     #[test]
     fn test_gap() {
@@ -280,4 +1064,527 @@ It was popularised in the 1960s with the release of Letraset sheets containing L
         assert_eq!(it.next(), Some("It has survived not only five centuries, but also the leap into electronic typesetting, remaining essentially unchanged."));
         assert_eq!(it.next(), Some("It was popularised in the 1960s with the release of Letraset sheets containing Lorem Ipsum passages, and more recently with desktop publishing software like Aldus PageMaker including versions of Lorem Ipsum."));
     }
+
+    fn buffer_lines(buffer: &Buffer) -> Vec<String> {
+        (0..buffer.line_count())
+            .map(|y| buffer.line(y).into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_buffer_insert_and_newline() {
+        let mut b = Buffer::new();
+        b.insert_text("hello");
+        b.newline();
+        b.insert_text("world");
+        assert_eq!(buffer_lines(&b), ["hello", "world"]);
+        assert_eq!((b.cursor.x, b.cursor.y), (5, 1));
+
+        b.move_up();
+        b.move_left();
+        b.move_left();
+        b.newline();
+        assert_eq!(buffer_lines(&b), ["hel", "lo", "world"]);
+        assert_eq!((b.cursor.x, b.cursor.y), (0, 1));
+
+        b.insert_text("a\nb\nc");
+        assert_eq!(buffer_lines(&b), ["hel", "a", "b", "clo", "world"]);
+        assert_eq!((b.cursor.x, b.cursor.y), (1, 3));
+    }
+
+    #[test]
+    fn test_buffer_backspace_and_delete() {
+        let mut b = Buffer::new();
+        b.insert_text("ab\ncd\nef");
+        b.set_cursor_offset(3);
+        b.backspace();
+        assert_eq!(buffer_lines(&b), ["abcd", "ef"]);
+        assert_eq!((b.cursor.x, b.cursor.y), (2, 0));
+
+        b.backspace();
+        assert_eq!(buffer_lines(&b), ["acd", "ef"]);
+        assert_eq!((b.cursor.x, b.cursor.y), (1, 0));
+
+        b.move_right();
+        b.move_right();
+        b.move_right();
+        assert_eq!((b.cursor.x, b.cursor.y), (3, 0));
+        b.delete();
+        assert_eq!(buffer_lines(&b), ["acdef"]);
+        b.delete();
+        b.delete();
+        assert_eq!(buffer_lines(&b), ["acd"]);
+        b.delete();
+        assert_eq!(buffer_lines(&b), ["acd"]);
+
+        b.set_cursor_offset(0);
+        b.backspace();
+        assert_eq!(buffer_lines(&b), ["acd"]);
+        assert_eq!((b.cursor.x, b.cursor.y), (0, 0));
+    }
+
+    #[test]
+    fn test_buffer_cursor_motions() {
+        let mut b = Buffer::new();
+        b.insert_text("long line\nab\nanother one");
+        assert_eq!((b.cursor.x, b.cursor.y), (11, 2));
+        b.move_up();
+        assert_eq!((b.cursor.x, b.cursor.y), (2, 1));
+        b.move_up();
+        assert_eq!((b.cursor.x, b.cursor.y), (9, 0));
+        b.move_up();
+        assert_eq!((b.cursor.x, b.cursor.y), (9, 0));
+        b.move_left();
+        b.move_down();
+        assert_eq!((b.cursor.x, b.cursor.y), (2, 1));
+        b.move_down();
+        assert_eq!((b.cursor.x, b.cursor.y), (8, 2));
+        b.move_down();
+        assert_eq!((b.cursor.x, b.cursor.y), (8, 2));
+        b.set_cursor_offset(0);
+        b.move_left();
+        assert_eq!((b.cursor.x, b.cursor.y), (0, 0));
+    }
+
+    #[test]
+    fn test_buffer_graphemes() {
+        let mut b = Buffer::new();
+        // precomposed é, e + combining acute, and a ZWJ family emoji
+        b.insert_text("\u{e9}e\u{301}\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}!");
+        assert_eq!(b.cursor.col, 4);
+        b.move_left();
+        b.move_left();
+        assert_eq!((b.cursor.x, b.cursor.col), (5, 2));
+        assert_eq!(
+            b.grapheme_at_cursor().as_deref(),
+            Some("\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}")
+        );
+        b.backspace();
+        assert_eq!(
+            b.line(0),
+            "\u{e9}\u{1f468}\u{200d}\u{1f469}\u{200d}\u{1f467}!"
+        );
+        assert_eq!((b.cursor.x, b.cursor.col), (2, 1));
+        b.delete();
+        assert_eq!(b.line(0), "\u{e9}!");
+        b.move_right();
+        b.move_right();
+        assert_eq!((b.cursor.x, b.cursor.col), (3, 2));
+        b.insert_text("\u{e9}");
+        assert_eq!(b.line(0), "\u{e9}!\u{e9}");
+    }
+
+    #[test]
+    fn test_buffer_goal_column_is_in_graphemes() {
+        let mut b = Buffer::new();
+        b.insert_text("abcd\n\u{e9}\u{e9}\n\u{e9}\u{e9}\u{e9}\u{e9}");
+        b.set_cursor_offset(3);
+        b.move_down();
+        assert_eq!((b.cursor.x, b.cursor.col), (4, 2));
+        b.move_down();
+        assert_eq!((b.cursor.x, b.cursor.col), (6, 3));
+        b.move_up();
+        b.move_up();
+        assert_eq!((b.cursor.x, b.cursor.col), (3, 3));
+    }
+
+    #[test]
+    fn test_buffer_edits_across_gap() {
+        let mut b = Buffer::new();
+        b.insert_text("first\nsecond\nthird");
+        // Leaves the gap in the middle of "second".
+        b.set_cursor_offset(9);
+        b.insert_text("X");
+        assert_eq!(b.line(1), "secXond");
+        b.set_cursor_offset(0);
+        b.insert_text("0");
+        assert_eq!(buffer_lines(&b), ["0first", "secXond", "third"]);
+        assert_eq!(b.grapheme_at_cursor().as_deref(), Some("f"));
+        b.set_cursor_offset(b.len());
+        b.backspace();
+        assert_eq!(buffer_lines(&b), ["0first", "secXond", "thir"]);
+    }
+
+    #[test]
+    fn test_buffer_undo_redo() {
+        let mut b = Buffer::new();
+        b.insert_text("h");
+        b.insert_text("i");
+        b.newline();
+        b.insert_text("there");
+        assert_eq!(buffer_lines(&b), ["hi", "there"]);
+
+        b.undo();
+        assert_eq!(buffer_lines(&b), ["hi", ""]);
+        b.undo();
+        assert_eq!(buffer_lines(&b), ["hi"]);
+        assert_eq!((b.cursor.x, b.cursor.y), (2, 0));
+        // "h" and "i" were typed in a row, so they go away together.
+        b.undo();
+        assert_eq!(buffer_lines(&b), [""]);
+        b.undo();
+        assert_eq!(buffer_lines(&b), [""]);
+
+        b.redo();
+        b.redo();
+        assert_eq!(buffer_lines(&b), ["hi", ""]);
+        assert_eq!((b.cursor.x, b.cursor.y), (0, 1));
+
+        b.backspace();
+        assert_eq!(buffer_lines(&b), ["hi"]);
+        // A new edit drops whatever could still be redone.
+        b.redo();
+        assert_eq!(buffer_lines(&b), ["hi"]);
+        b.undo();
+        assert_eq!(buffer_lines(&b), ["hi", ""]);
+        assert_eq!((b.cursor.x, b.cursor.y), (0, 1));
+    }
+
+    #[test]
+    fn test_buffer_undo_coalescing_stops_on_motion() {
+        let mut b = Buffer::new();
+        b.insert_text("ab");
+        b.move_left();
+        b.move_right();
+        b.insert_text("c");
+        b.insert_text("d");
+        b.delete();
+        b.move_left();
+        b.delete();
+        assert_eq!(buffer_lines(&b), ["abc"]);
+        b.undo();
+        assert_eq!(buffer_lines(&b), ["abcd"]);
+        assert_eq!((b.cursor.x, b.cursor.y), (3, 0));
+        b.undo();
+        assert_eq!(buffer_lines(&b), ["ab"]);
+        b.undo();
+        assert_eq!(buffer_lines(&b), [""]);
+    }
+
+    #[test]
+    fn test_buffer_selection() {
+        let mut b = Buffer::new();
+        b.insert_text("one\ntwo\nthree");
+        b.set_cursor_offset(1);
+        b.start_selection();
+        b.move_down();
+        b.move_right();
+        assert_eq!(b.selection(), Some(1..6));
+        assert_eq!(b.selected_text().as_deref(), Some("ne\ntw"));
+        assert_eq!(b.selection_on_line(0), Some(1..3));
+        assert_eq!(b.selection_on_line(1), Some(0..2));
+        assert_eq!(b.selection_on_line(2), None);
+
+        b.insert_text("X");
+        assert_eq!(buffer_lines(&b), ["oXo", "three"]);
+        assert_eq!(b.selection(), None);
+        assert_eq!((b.cursor.x, b.cursor.y), (2, 0));
+
+        // Selecting backwards works the same way.
+        b.start_selection();
+        b.move_left();
+        b.move_left();
+        assert_eq!(b.selected_text().as_deref(), Some("oX"));
+        b.backspace();
+        assert_eq!(buffer_lines(&b), ["o", "three"]);
+
+        b.undo();
+        assert_eq!(buffer_lines(&b), ["oXo", "three"]);
+        b.undo();
+        assert_eq!(buffer_lines(&b), ["one", "two", "three"]);
+    }
+
+    #[test]
+    fn test_buffer_region_operations() {
+        let mut b = Buffer::new();
+        b.insert_text("hello world");
+        b.replace_range(0..5, "goodbye\ncruel");
+        assert_eq!(buffer_lines(&b), ["goodbye", "cruel world"]);
+        assert_eq!((b.cursor.x, b.cursor.y), (5, 1));
+        b.delete_range(7..13);
+        assert_eq!(buffer_lines(&b), ["goodbye world"]);
+        b.undo();
+        b.undo();
+        assert_eq!(buffer_lines(&b), ["hello world"]);
+    }
+
+    #[test]
+    fn test_buffer_copy_cut_paste() {
+        let mut b = Buffer::new();
+        b.insert_text("first\nsecond\nthird");
+        b.set_cursor_offset(7);
+        assert_eq!(b.copy(), "second\n");
+        assert_eq!(b.cut(), "second\n");
+        assert_eq!(buffer_lines(&b), ["first", "third"]);
+        assert_eq!((b.cursor.x, b.cursor.y), (0, 1));
+
+        b.paste("a\r\nb\rc\n");
+        assert_eq!(buffer_lines(&b), ["first", "a", "b", "c", "third"]);
+        assert_eq!((b.cursor.x, b.cursor.y), (0, 4));
+
+        b.move_down();
+        b.cut();
+        assert_eq!(buffer_lines(&b), ["first", "a", "b", "c"]);
+
+        b.set_cursor_offset(1);
+        b.start_selection();
+        b.move_down();
+        assert_eq!(b.copy(), "irst\na");
+        b.paste("X");
+        assert_eq!(buffer_lines(&b), ["fX", "b", "c"]);
+        b.undo();
+        assert_eq!(buffer_lines(&b), ["first", "a", "b", "c"]);
+    }
+
+    fn round_trip(name: &str, contents: &[u8]) -> Buffer {
+        let dir = TestDir::new(name);
+        std::fs::write(dir.join("file"), contents).unwrap();
+        let mut buffer = Buffer::from_filepath(dir.file("file")).unwrap();
+        buffer.save().unwrap();
+        assert_eq!(std::fs::read(dir.join("file")).unwrap(), contents);
+        buffer
+    }
+
+    #[test]
+    fn test_buffer_round_trips_files() {
+        let b = round_trip("lf", b"one\ntwo\n");
+        assert_eq!(buffer_lines(&b), ["one", "two"]);
+        assert_eq!(b.line_ending, LineEnding::Lf);
+        assert!(b.trailing_newline);
+
+        let b = round_trip("crlf", b"one\r\ntwo\r\n\r\n");
+        assert_eq!(buffer_lines(&b), ["one", "two", ""]);
+        assert_eq!(b.line_ending, LineEnding::CrLf);
+
+        let b = round_trip("no-trailing-newline", b"one\ntwo");
+        assert_eq!(buffer_lines(&b), ["one", "two"]);
+        assert!(!b.trailing_newline);
+
+        let b = round_trip("bom", b"\xef\xbb\xbfone\r\ntwo");
+        assert_eq!(buffer_lines(&b), ["one", "two"]);
+        assert!(b.bom);
+
+        let b = round_trip("mixed", b"one\r\ntwo\nthree\r\n");
+        assert_eq!(b.line_ending, LineEnding::Lf);
+        assert_eq!(buffer_lines(&b), ["one\r", "two", "three\r"]);
+
+        round_trip("empty", b"");
+        round_trip("only-newline", b"\n");
+    }
+
+    #[test]
+    fn test_buffer_converts_line_endings() {
+        let mut b = Buffer::new();
+        b.insert_text("one\ntwo");
+        let mut out = vec![];
+        b.write_to(&mut out).unwrap();
+        assert_eq!(out, b"one\ntwo\n");
+
+        b.set_line_ending(LineEnding::CrLf);
+        // Moves the gap in between the lines.
+        b.set_cursor_offset(2);
+        b.insert_text("\n");
+        let mut out = vec![];
+        b.write_to(&mut out).unwrap();
+        assert_eq!(out, b"on\r\ne\r\ntwo\r\n");
+    }
+
+    #[test]
+    fn test_buffer_save_replaces_file_atomically() {
+        let dir = TestDir::new("save");
+        let path = dir.join("script.sh");
+        fs::write(&path, "old\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o750)).unwrap();
+        }
+
+        let mut b = Buffer::from_filepath(path.to_string_lossy().into_owned()).unwrap();
+        b.insert_text("new ");
+        b.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new old\n");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o750);
+        }
+        // Nothing but the file itself is left behind.
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let mut b = Buffer::new();
+        assert_eq!(b.save().unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        let other = dir.join("other.txt");
+        b.save_as(other.clone()).unwrap();
+        assert_eq!(fs::read_to_string(&other).unwrap(), "\n");
+
+        // A failed save as stays on the old path and keeps its journal.
+        b.insert_text("unsaved");
+        b.write_swap().unwrap();
+        assert!(b.save_as(dir.join("missing").join("x.txt")).is_err());
+        assert_eq!(b.filepath(), Some(other.as_path()));
+        assert!(swap::swap_path(&other).exists());
+        b.save_as(dir.join("moved.txt")).unwrap();
+        assert!(!swap::swap_path(&other).exists());
+    }
+
+    #[test]
+    fn test_buffer_modified_flag() {
+        let dir = TestDir::new("modified");
+        let path = dir.join("file");
+        let mut b = Buffer::new();
+        assert!(!b.is_modified());
+        b.insert_text("ab");
+        assert!(b.is_modified());
+        b.save_as(path.clone()).unwrap();
+        assert!(!b.is_modified());
+
+        b.insert_text("c");
+        assert!(b.is_modified());
+        b.undo();
+        assert!(!b.is_modified());
+        b.undo();
+        assert!(b.is_modified());
+        b.redo();
+        assert!(!b.is_modified());
+
+        b.set_line_ending(LineEnding::CrLf);
+        assert!(b.is_modified());
+        b.set_line_ending(LineEnding::Lf);
+        assert!(!b.is_modified());
+
+        // Once the redo history is gone, nothing leads back to the saved text.
+        b.undo();
+        b.insert_text("b");
+        assert!(b.is_modified());
+    }
+
+    #[test]
+    fn test_buffer_swap_recovery() {
+        let dir = TestDir::new("swap");
+        let path = dir.join("notes.txt");
+        let swap_path = dir.join(".notes.txt.red-swp");
+        fs::write(&path, "one\r\ntwo\r\n").unwrap();
+
+        let mut b = Buffer::from_filepath(path.to_string_lossy().into_owned()).unwrap();
+        b.write_swap().unwrap();
+        assert!(!swap_path.exists());
+        b.insert_text("zero ");
+        b.write_swap().unwrap();
+        assert_eq!(
+            fs::read_to_string(&swap_path).unwrap(),
+            "zero one\r\ntwo\r\n"
+        );
+
+        // The session dies here, without saving.
+        let mut b = Buffer::from_filepath(path.to_string_lossy().into_owned()).unwrap();
+        let recovery = b.recovery.as_ref().unwrap();
+        assert_eq!(
+            recovery.summary.to_string(),
+            "+1 -1 lines, first change at line 1"
+        );
+        // Still there while deciding.
+        b.write_swap().unwrap();
+        assert!(swap_path.exists());
+        b.recover();
+        assert_eq!(buffer_lines(&b), ["zero one", "two"]);
+        assert_eq!(b.line_ending, LineEnding::CrLf);
+        assert!(b.is_modified());
+        b.undo();
+        assert_eq!(buffer_lines(&b), ["one", "two"]);
+        b.redo();
+
+        b.save().unwrap();
+        assert!(!swap_path.exists());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "zero one\r\ntwo\r\n");
+
+        b.insert_text("!");
+        b.write_swap().unwrap();
+        let mut b = Buffer::from_filepath(path.to_string_lossy().into_owned()).unwrap();
+        b.discard_recovery().unwrap();
+        assert!(!swap_path.exists());
+        assert_eq!(buffer_lines(&b), ["zero one", "two"]);
+    }
+
+    #[test]
+    fn test_buffer_swap_recovery_of_new_file() {
+        let dir = TestDir::new("swap-new");
+        fs::write(dir.join(".new.txt.red-swp"), "never saved\n").unwrap();
+        let mut b = Buffer::from_filepath(dir.file("new.txt")).unwrap();
+        assert!(b.recovery.is_some());
+        // The journal stays until it's dealt with.
+        b.write_swap().unwrap();
+        assert!(dir.join(".new.txt.red-swp").exists());
+        b.recover();
+        assert_eq!(buffer_lines(&b), ["never saved"]);
+    }
+
+    #[test]
+    fn test_buffer_notices_changes_on_disk() {
+        let dir = TestDir::new("disk");
+        let path = dir.join("file");
+        fs::write(&path, "one\n").unwrap();
+        let mut b = Buffer::from_filepath(path.to_string_lossy().into_owned()).unwrap();
+        assert_eq!(b.check_disk().unwrap(), None);
+
+        // Clean buffers just follow the file.
+        fs::write(&path, "one\r\ntwo\r\n").unwrap();
+        assert!(b.changed_on_disk());
+        assert_eq!(b.check_disk().unwrap(), Some(DiskChange::Reloaded));
+        assert_eq!(buffer_lines(&b), ["one", "two"]);
+        assert_eq!(b.line_ending, LineEnding::CrLf);
+        assert!(!b.is_modified());
+        assert!(!b.changed_on_disk());
+
+        // Dirty ones get it reported, once.
+        b.insert_text("zero ");
+        fs::write(&path, "three\n").unwrap();
+        assert_eq!(b.check_disk().unwrap(), Some(DiskChange::Conflict));
+        assert_eq!(b.check_disk().unwrap(), None);
+        assert!(b.changed_on_disk());
+        assert_eq!(buffer_lines(&b), ["zero one", "two"]);
+        b.save().unwrap();
+        assert!(!b.changed_on_disk());
+    }
+
+    #[test]
+    fn test_buffer_polls_disk_from_the_start() {
+        let dir = TestDir::new("poll");
+        let path = dir.join("file");
+        fs::write(&path, "one\n").unwrap();
+        let mut b = Buffer::from_filepath(dir.file("file")).unwrap();
+        // Changed before anything was watching it.
+        fs::write(&path, "two\nthree\n").unwrap();
+        assert_eq!(b.poll_disk().unwrap(), Some(DiskChange::Reloaded));
+        assert_eq!(buffer_lines(&b), ["two", "three"]);
+        assert_eq!(b.poll_disk().unwrap(), None);
+    }
+
+    #[test]
+    fn test_buffer_anchors_follow_edits() {
+        let mut b = Buffer::new();
+        b.insert_text("one two\nthree");
+        b.set_cursor_offset(4);
+        b.start_selection();
+        b.move_right();
+        let other = b.anchor();
+
+        // Edited from somewhere else.
+        b.clear_selection();
+        b.set_cursor_offset(0);
+        b.insert_text("zero ");
+        b.set_cursor_offset(b.len());
+        b.insert_text("!");
+        b.delete_range(7..13);
+        assert_eq!(b.text_range(0..b.len()), "zero onthree!");
+
+        let other = b.follow(other);
+        assert_eq!(b.anchor_cursor(other), b.cursor_at(7));
+        // Text going back in right where it is goes after it.
+        b.undo();
+        b.restore(other);
+        assert_eq!(b.cursor_offset(), 7);
+        assert_eq!(b.text_range(7..13), "e two\n");
+    }
 }
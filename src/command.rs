@@ -0,0 +1,111 @@
+// Everything that can be bound to a key, or run by name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Command {
+    CursorLeft,
+    CursorRight,
+    CursorUp,
+    CursorDown,
+    SelectLeft,
+    SelectRight,
+    SelectUp,
+    SelectDown,
+    Newline,
+    Backspace,
+    Delete,
+    Undo,
+    Redo,
+    Copy,
+    Cut,
+    Paste,
+    Search,
+    Save,
+    SaveAs,
+    Open,
+    Close,
+    ListBuffers,
+    NextBuffer,
+    PrevBuffer,
+    ToggleLineEnding,
+    ToggleLineNumbers,
+    NextTheme,
+    SplitHorizontal,
+    SplitVertical,
+    ClosePane,
+    NextPane,
+    PrevPane,
+    GotoLine,
+    Palette,
+    Quit,
+}
+
+pub const COMMANDS: &[(Command, &str)] = &[
+    (Command::CursorLeft, "cursor.left"),
+    (Command::CursorRight, "cursor.right"),
+    (Command::CursorUp, "cursor.up"),
+    (Command::CursorDown, "cursor.down"),
+    (Command::SelectLeft, "select.left"),
+    (Command::SelectRight, "select.right"),
+    (Command::SelectUp, "select.up"),
+    (Command::SelectDown, "select.down"),
+    (Command::Newline, "edit.newline"),
+    (Command::Backspace, "edit.backspace"),
+    (Command::Delete, "edit.delete"),
+    (Command::Undo, "edit.undo"),
+    (Command::Redo, "edit.redo"),
+    (Command::Copy, "edit.copy"),
+    (Command::Cut, "edit.cut"),
+    (Command::Paste, "edit.paste"),
+    (Command::Search, "search.start"),
+    (Command::Save, "buffer.save"),
+    (Command::SaveAs, "buffer.save_as"),
+    (Command::Open, "buffer.open"),
+    (Command::Close, "buffer.close"),
+    (Command::ListBuffers, "buffer.list"),
+    (Command::NextBuffer, "buffer.next"),
+    (Command::PrevBuffer, "buffer.prev"),
+    (Command::ToggleLineEnding, "buffer.toggle_line_ending"),
+    (Command::ToggleLineNumbers, "view.toggle_line_numbers"),
+    (Command::NextTheme, "view.next_theme"),
+    (Command::SplitHorizontal, "pane.split_horizontal"),
+    (Command::SplitVertical, "pane.split_vertical"),
+    (Command::ClosePane, "pane.close"),
+    (Command::NextPane, "pane.next"),
+    (Command::PrevPane, "pane.prev"),
+    (Command::GotoLine, "cursor.goto_line"),
+    (Command::Palette, "editor.palette"),
+    (Command::Quit, "editor.quit"),
+];
+
+impl Command {
+    pub fn name(self) -> &'static str {
+        COMMANDS
+            .iter()
+            .find(|(command, _)| *command == self)
+            .map(|(_, name)| *name)
+            .expect("every command has a name")
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        COMMANDS
+            .iter()
+            .find(|(_, n)| *n == name)
+            .map(|(command, _)| *command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_names() {
+        for (i, &(command, name)) in COMMANDS.iter().enumerate() {
+            assert_eq!(command.name(), name);
+            assert_eq!(Command::from_name(name), Some(command));
+            assert!(COMMANDS[..i]
+                .iter()
+                .all(|&(c, n)| c != command && n != name));
+        }
+        assert_eq!(Command::from_name("buffer.nope"), None);
+    }
+}
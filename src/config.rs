@@ -0,0 +1,394 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::gutter::LineNumbers;
+use crate::theme::Theme;
+use crate::toml::{self, Table, Value};
+use crate::Color;
+
+// `~/.config/red` unless XDG says otherwise.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("red"))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    pub line_numbers: LineNumbers,
+    pub font: Font,
+    // Name of the theme to start with.
+    pub theme: String,
+    // Colors that differ from the theme's, whichever it is.
+    pub colors: Table,
+}
+
+// Either a TrueType or OpenType `file` rasterized `size` pixels tall, a BDF
+// or PSF bitmap `file`, or an image with a grid of printable ASCII, starting
+// at space. Bitmap fonts get scaled up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Font {
+    pub file: String,
+    pub size: f32,
+    pub atlas: String,
+    pub columns: u32,
+    pub rows: u32,
+    pub scale: f32,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            width: 1280,
+            height: 720,
+            fps: 60,
+            line_numbers: LineNumbers::Absolute,
+            font: Font::default(),
+            theme: "dark".into(),
+            colors: Table::new(),
+        }
+    }
+}
+
+// The bitmap font that comes with red.
+impl Default for Font {
+    fn default() -> Self {
+        Self {
+            file: String::new(),
+            size: 16.0,
+            atlas: "charmap-oldschool_white.png".into(),
+            columns: 18,
+            rows: 7,
+            scale: 3.0,
+        }
+    }
+}
+
+impl Config {
+    // What's in `path`, or the defaults when there's no such file.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text).map_err(|err| format!("{}: {}", path.display(), err)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(format!("{}: {}", path.display(), err)),
+        }
+    }
+
+    // Everything that's wrong with it is reported at once, one per line.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let table = toml::parse(text)?;
+        let mut config = Self::default();
+        let mut errors = vec![];
+        let mut fields = Fields {
+            table: &table,
+            prefix: "",
+            errors: &mut errors,
+        };
+        fields.number("width", &mut config.width, 1..=16384);
+        fields.number("height", &mut config.height, 1..=16384);
+        fields.number("fps", &mut config.fps, 1..=1000);
+        let mut line_numbers = config.line_numbers.name().to_string();
+        fields.string("line_numbers", &mut line_numbers);
+        match LineNumbers::from_name(&line_numbers) {
+            Some(value) => config.line_numbers = value,
+            None => fields.error(
+                "line_numbers",
+                format!("`{}` isn't absolute, relative or off", line_numbers),
+            ),
+        }
+        fields.table("font", |fields| {
+            let font = &mut config.font;
+            fields.string("file", &mut font.file);
+            fields.float("size", &mut font.size, 4.0..=256.0);
+            fields.string("atlas", &mut font.atlas);
+            fields.number("columns", &mut font.columns, 1..=256);
+            fields.number("rows", &mut font.rows, 1..=256);
+            fields.float("scale", &mut font.scale, 0.1..=64.0);
+            fields.unknown(&["file", "size", "atlas", "columns", "rows", "scale"]);
+        });
+        fields.string("theme", &mut config.theme);
+        fields.table("colors", |fields| {
+            Theme::default().read(fields);
+            config.colors = fields.table.clone();
+        });
+        fields.unknown(&[
+            "width",
+            "height",
+            "fps",
+            "line_numbers",
+            "font",
+            "theme",
+            "colors",
+        ]);
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+}
+
+// `#rrggbb` or `#rrggbbaa`.
+pub fn parse_color(s: &str) -> Option<Color> {
+    let hex = s.strip_prefix('#').filter(|hex| hex.is_ascii())?;
+    if hex.len() != 6 && hex.len() != 8 {
+        return None;
+    }
+    let mut channels = [255u8; 4];
+    for (i, channel) in channels.iter_mut().enumerate().take(hex.len() / 2) {
+        *channel = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    let [r, g, b, a] = channels.map(|c| c as f32 / 255.0);
+    Some(Color::new(r, g, b, a))
+}
+
+// Parses each `*.toml` file in `dir` with `parse(name, text)`, the name
+// being the file's minus the extension. Files come in order and the ones
+// that don't parse only end up in the errors. It's fine for `dir` not to
+// be there.
+pub fn load_toml_dir<T>(
+    dir: &Path,
+    parse: impl Fn(&str, &str) -> Result<T, String>,
+) -> (Vec<T>, Vec<String>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return (vec![], vec![]),
+        Err(err) => return (vec![], vec![format!("{}: {}", dir.display(), err)]),
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+        .collect();
+    paths.sort();
+    let mut parsed = vec![];
+    let mut errors = vec![];
+    for path in paths {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        let result = fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| parse(&name, &text));
+        match result {
+            Ok(value) => parsed.push(value),
+            Err(err) => errors.push(format!("{}: {}", path.display(), err)),
+        }
+    }
+    (parsed, errors)
+}
+
+// Reads the fields of a table into where they go, keeping track of
+// what's wrong with them.
+pub struct Fields<'a> {
+    pub table: &'a Table,
+    pub prefix: &'a str,
+    pub errors: &'a mut Vec<String>,
+}
+
+impl<'a> Fields<'a> {
+    fn get(&mut self, key: &str, expected: &str) -> Option<&'a Value> {
+        let value = self.table.get(key)?;
+        let ok = match value {
+            Value::String(_) => expected == "a string",
+            Value::Integer(_) => expected == "an integer" || expected == "a number",
+            Value::Float(_) => expected == "a number",
+            Value::Boolean(_) => expected == "a boolean",
+            Value::Array(_) => expected == "an array",
+            Value::Table(_) => expected == "a table",
+        };
+        if !ok {
+            self.error(
+                key,
+                format!("expected {}, not {}", expected, value.type_name()),
+            );
+            return None;
+        }
+        Some(value)
+    }
+
+    pub fn error(&mut self, key: &str, message: String) {
+        self.errors
+            .push(format!("{}{}: {}", self.prefix, key, message));
+    }
+
+    pub fn string(&mut self, key: &str, out: &mut String) {
+        if let Some(Value::String(s)) = self.get(key, "a string") {
+            out.clone_from(s);
+        }
+    }
+
+    pub fn number(&mut self, key: &str, out: &mut u32, range: std::ops::RangeInclusive<u32>) {
+        if let Some(Value::Integer(n)) = self.get(key, "an integer") {
+            match u32::try_from(*n).ok().filter(|n| range.contains(n)) {
+                Some(n) => *out = n,
+                None => self.error(
+                    key,
+                    format!("{} isn't between {} and {}", n, range.start(), range.end()),
+                ),
+            }
+        }
+    }
+
+    pub fn float(&mut self, key: &str, out: &mut f32, range: std::ops::RangeInclusive<f32>) {
+        let n = match self.get(key, "a number") {
+            Some(Value::Integer(n)) => *n as f32,
+            Some(Value::Float(n)) => *n as f32,
+            _ => return,
+        };
+        if range.contains(&n) {
+            *out = n;
+        } else {
+            self.error(
+                key,
+                format!("{} isn't between {} and {}", n, range.start(), range.end()),
+            );
+        }
+    }
+
+    pub fn boolean(&mut self, key: &str, out: &mut bool) {
+        if let Some(Value::Boolean(b)) = self.get(key, "a boolean") {
+            *out = *b;
+        }
+    }
+
+    pub fn strings(&mut self, key: &str, out: &mut Vec<String>) {
+        if let Some(Value::Array(values)) = self.get(key, "an array") {
+            let strings: Option<Vec<String>> = values
+                .iter()
+                .map(|value| match value {
+                    Value::String(s) => Some(s.clone()),
+                    _ => None,
+                })
+                .collect();
+            match strings {
+                Some(strings) => *out = strings,
+                None => self.error(key, "expected an array of strings".into()),
+            }
+        }
+    }
+
+    pub fn color(&mut self, key: &str, out: &mut Color) {
+        if let Some(Value::String(s)) = self.get(key, "a string") {
+            match parse_color(s) {
+                Some(color) => *out = color,
+                None => self.error(key, format!("`{}` isn't a #rrggbb color", s)),
+            }
+        }
+    }
+
+    pub fn table(&mut self, key: &str, f: impl FnOnce(&mut Fields)) {
+        if let Some(Value::Table(table)) = self.get(key, "a table") {
+            let prefix = format!("{}{}.", self.prefix, key);
+            f(&mut Fields {
+                table,
+                prefix: &prefix,
+                errors: self.errors,
+            });
+        }
+    }
+
+    // Each table of an array of them, like `[[key]]`.
+    pub fn tables(&mut self, key: &str, mut f: impl FnMut(&mut Fields)) {
+        if let Some(Value::Array(values)) = self.get(key, "an array") {
+            for (i, value) in values.iter().enumerate() {
+                let prefix = format!("{}{}[{}].", self.prefix, key, i);
+                match value {
+                    Value::Table(table) => f(&mut Fields {
+                        table,
+                        prefix: &prefix,
+                        errors: self.errors,
+                    }),
+                    _ => self.errors.push(format!(
+                        "{}{}[{}]: expected a table, not {}",
+                        self.prefix,
+                        key,
+                        i,
+                        value.type_name()
+                    )),
+                }
+            }
+        }
+    }
+
+    // Typos would otherwise go unnoticed.
+    pub fn unknown(&mut self, known: &[&str]) {
+        for key in self.table.keys() {
+            if !known.contains(&key.as_str()) {
+                self.errors
+                    .push(format!("{}{}: unknown setting", self.prefix, key));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_config() {
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        let config = Config::parse(
+            "fps = 30\nline_numbers = \"relative\"\n[font]\nscale = 2\natlas = \"font.png\"\n[colors]\nbackground = \"#ff8000\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.fps, 30);
+        assert_eq!(config.line_numbers, LineNumbers::Relative);
+        assert_eq!(config.font.scale, 2.0);
+        assert_eq!(config.font.atlas, "font.png");
+        assert_eq!(config.font.rows, 7);
+        assert_eq!(config.theme, "dark");
+        let theme = Theme::default().with(&config.colors);
+        assert_eq!(theme.background, Color::new(1.0, 128.0 / 255.0, 0.0, 1.0));
+
+        assert_eq!(
+            Config::parse(
+                "fps = 0\nwidht = 800\n[font]\nscale = \"big\"\n[colors]\ntext = \"white\""
+            )
+            .unwrap_err(),
+            "fps: 0 isn't between 1 and 1000\n\
+             font.scale: expected a number, not a string\n\
+             colors.text: `white` isn't a #rrggbb color\n\
+             widht: unknown setting"
+        );
+        assert_eq!(
+            Config::parse("fps = ").unwrap_err(),
+            "line 1: expected a value"
+        );
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("#ffffff"), Some(crate::WHITE));
+        assert_eq!(parse_color("#00000080").map(|c| c.w), Some(128.0 / 255.0));
+        assert_eq!(parse_color("ffffff"), None);
+        assert_eq!(parse_color("#fff"), None);
+        assert_eq!(parse_color("#gggggg"), None);
+    }
+
+    #[test]
+    fn test_load_toml_dir() {
+        let dir = TestDir::new("toml-dir");
+        let parse = |name: &str, text: &str| toml::parse(text).map(|_| name.to_string());
+        assert_eq!(load_toml_dir(&dir.join("missing"), parse), (vec![], vec![]));
+
+        fs::write(dir.join("b.toml"), "x = 1").unwrap();
+        fs::write(dir.join("a.toml"), "x = 1").unwrap();
+        fs::write(dir.join("c.toml"), "x =").unwrap();
+        fs::write(dir.join("d.txt"), "x = 1").unwrap();
+        let (names, errors) = load_toml_dir(dir.path(), parse);
+        assert_eq!(names, ["a", "b"]);
+        assert_eq!(
+            errors,
+            [format!(
+                "{}: line 1: expected a value",
+                dir.join("c.toml").display()
+            )]
+        );
+    }
+}
@@ -0,0 +1,391 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::buffer::{Anchor, Buffer, Cursor};
+use crate::highlight::Highlighter;
+use crate::layout::{Layout, Split};
+use crate::Vector2;
+
+// A buffer along with where it was last looked at.
+pub struct Document {
+    pub buffer: Buffer,
+    pub camera_pos: Vector2<f32>,
+    pub highlighter: Highlighter,
+}
+
+impl Document {
+    pub fn new(buffer: Buffer) -> Self {
+        Self {
+            buffer,
+            camera_pos: Vector2::default(),
+            highlighter: Highlighter::new(),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        self.buffer.filepath().map_or("untitled".into(), |path| {
+            path.to_string_lossy().into_owned()
+        })
+    }
+}
+
+// What a pane shows. The focused view's cursor is the one in its buffer,
+// the others keep theirs in `anchor`.
+pub struct View {
+    pub document: usize,
+    anchor: Anchor,
+    pub camera_pos: Vector2<f32>,
+}
+
+// Every open buffer, shown in one or more views, one of which is being edited.
+// There's always at least one of each.
+pub struct Editor {
+    documents: Vec<Document>,
+    views: Vec<View>,
+    layout: Layout,
+    focused: usize,
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Editor {
+    pub fn new() -> Self {
+        Self {
+            documents: vec![Document::new(Buffer::new())],
+            views: vec![View {
+                document: 0,
+                anchor: Anchor::default(),
+                camera_pos: Vector2::default(),
+            }],
+            layout: Layout::Pane(0),
+            focused: 0,
+        }
+    }
+
+    pub fn documents(&self) -> &[Document] {
+        &self.documents
+    }
+
+    pub fn documents_mut(&mut self) -> &mut [Document] {
+        &mut self.documents
+    }
+
+    pub fn current_index(&self) -> usize {
+        self.views[self.focused].document
+    }
+
+    pub fn current(&self) -> &Document {
+        &self.documents[self.current_index()]
+    }
+
+    pub fn current_mut(&mut self) -> &mut Document {
+        let i = self.current_index();
+        &mut self.documents[i]
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.current().buffer
+    }
+
+    pub fn buffer_mut(&mut self) -> &mut Buffer {
+        &mut self.current_mut().buffer
+    }
+
+    // Switches to `filepath`, opening it unless it already is.
+    pub fn open(&mut self, filepath: String) -> io::Result<()> {
+        if let Some(i) = self.find(Path::new(&filepath)) {
+            self.show(i);
+            return Ok(());
+        }
+        let buffer = Buffer::from_filepath(filepath)?;
+        // The untitled buffer there is when nothing was opened yet just makes way.
+        if self.documents.len() == 1 && self.is_pristine(0) {
+            self.documents[0] = Document::new(buffer);
+            for view in &mut self.views {
+                view.anchor = Anchor::default();
+            }
+        } else {
+            self.documents.push(Document::new(buffer));
+            self.show(self.documents.len() - 1);
+        }
+        Ok(())
+    }
+
+    fn find(&self, filepath: &Path) -> Option<usize> {
+        let canonical = fs::canonicalize(filepath).ok();
+        self.documents.iter().position(|document| {
+            document.buffer.filepath().is_some_and(|path| {
+                path == filepath || canonical.is_some() && fs::canonicalize(path).ok() == canonical
+            })
+        })
+    }
+
+    fn is_pristine(&self, i: usize) -> bool {
+        let buffer = &self.documents[i].buffer;
+        buffer.filepath().is_none() && buffer.is_empty() && !buffer.is_modified()
+    }
+
+    // Shows the `i`th buffer in the focused view, where it was last left.
+    fn show(&mut self, i: usize) {
+        let view = &mut self.views[self.focused];
+        if view.document == i {
+            return;
+        }
+        self.documents[view.document].camera_pos = view.camera_pos;
+        view.document = i;
+        view.camera_pos = self.documents[i].camera_pos;
+    }
+
+    pub fn switch_to(&mut self, i: usize) {
+        if i < self.documents.len() {
+            self.show(i);
+        }
+    }
+
+    pub fn next(&mut self) {
+        self.show((self.current_index() + 1) % self.documents.len());
+    }
+
+    pub fn prev(&mut self) {
+        let len = self.documents.len();
+        self.show((self.current_index() + len - 1) % len);
+    }
+
+    // Drops the current buffer whether it has unsaved changes or not,
+    // along with its swap file since that's deliberate.
+    // Views of it move on to a neighbour.
+    pub fn close_current(&mut self) -> io::Result<()> {
+        let closed = self.current_index();
+        let mut document = self.documents.remove(closed);
+        if self.documents.is_empty() {
+            self.documents.push(Document::new(Buffer::new()));
+        }
+        let neighbour = closed.min(self.documents.len() - 1);
+        for view in &mut self.views {
+            if view.document == closed {
+                view.document = neighbour;
+                view.anchor = self.documents[neighbour].buffer.anchor();
+                view.camera_pos = self.documents[neighbour].camera_pos;
+            } else if view.document > closed {
+                view.document -= 1;
+            }
+        }
+        document.buffer.remove_swap()
+    }
+
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    pub fn views(&self) -> &[View] {
+        &self.views
+    }
+
+    pub fn view_mut(&mut self, v: usize) -> &mut View {
+        &mut self.views[v]
+    }
+
+    pub fn focused(&self) -> usize {
+        self.focused
+    }
+
+    pub fn view_cursor(&self, v: usize) -> Cursor {
+        let view = &self.views[v];
+        let buffer = &self.documents[view.document].buffer;
+        if v == self.focused {
+            buffer.cursor
+        } else {
+            buffer.anchor_cursor(view.anchor)
+        }
+    }
+
+    // Keeps the views that aren't focused up with the edits made in the one that is.
+    pub fn sync_views(&mut self) {
+        for (v, view) in self.views.iter_mut().enumerate() {
+            if v != self.focused {
+                view.anchor = self.documents[view.document].buffer.follow(view.anchor);
+            }
+        }
+    }
+
+    pub fn focus(&mut self, v: usize) {
+        if v == self.focused || v >= self.views.len() {
+            return;
+        }
+        let old = &mut self.views[self.focused];
+        old.anchor = self.documents[old.document].buffer.anchor();
+        self.focused = v;
+        let new = &self.views[v];
+        self.documents[new.document].buffer.restore(new.anchor);
+    }
+
+    pub fn focus_next(&mut self) {
+        let panes = self.layout.panes();
+        let i = panes.iter().position(|&p| p == self.focused).unwrap_or(0);
+        self.focus(panes[(i + 1) % panes.len()]);
+    }
+
+    pub fn focus_prev(&mut self) {
+        let panes = self.layout.panes();
+        let i = panes.iter().position(|&p| p == self.focused).unwrap_or(0);
+        self.focus(panes[(i + panes.len() - 1) % panes.len()]);
+    }
+
+    // Splits the focused pane in two, both showing the same thing,
+    // and focuses the new one.
+    pub fn split(&mut self, split: Split) {
+        let view = &self.views[self.focused];
+        let new = View {
+            document: view.document,
+            anchor: self.documents[view.document].buffer.anchor(),
+            camera_pos: view.camera_pos,
+        };
+        self.views.push(new);
+        let new = self.views.len() - 1;
+        self.layout.split(self.focused, split, new);
+        self.focus(new);
+    }
+
+    // Closes the focused pane unless it's the only one, the buffer stays open.
+    pub fn close_pane(&mut self) {
+        if self.views.len() == 1 {
+            return;
+        }
+        let closed = self.focused;
+        self.focus_next();
+        self.views.remove(closed);
+        self.layout.remove(closed);
+        if self.focused > closed {
+            self.focused -= 1;
+        }
+    }
+
+    pub fn first_modified(&self) -> Option<usize> {
+        self.documents
+            .iter()
+            .position(|document| document.buffer.is_modified())
+    }
+
+    // Something like "1 main.rs, >*2 lib.rs", pointing at the current one
+    // and marking the unsaved ones.
+    pub fn list(&self) -> String {
+        let current = self.current_index();
+        self.documents
+            .iter()
+            .enumerate()
+            .map(|(i, document)| {
+                format!(
+                    "{}{}{} {}",
+                    if i == current { ">" } else { "" },
+                    if document.buffer.is_modified() {
+                        "*"
+                    } else {
+                        ""
+                    },
+                    i + 1,
+                    document.name(),
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_editor_buffers() {
+        let dir = TestDir::new("editor");
+        let path = |name: &str| dir.file(name);
+        fs::write(path("a.txt"), "").unwrap();
+        fs::write(path("b.txt"), "").unwrap();
+
+        let mut editor = Editor::new();
+        editor.open(path("a.txt")).unwrap();
+        assert_eq!(editor.documents().len(), 1);
+        editor.open(path("b.txt")).unwrap();
+        editor.open(path("c.txt")).unwrap();
+        // Replaces the empty buffer it started with.
+        assert_eq!(editor.documents().len(), 3);
+        assert_eq!(editor.current_index(), 2);
+
+        editor.buffer_mut().insert_text("c");
+        editor.next();
+        assert_eq!(editor.current_index(), 0);
+        editor.prev();
+        editor.prev();
+        assert_eq!(editor.current_index(), 1);
+        editor.buffer_mut().insert_text("b");
+        editor.buffer_mut().move_left();
+
+        // Opening it again goes back to it, cursor and all.
+        editor.open(path("c.txt")).unwrap();
+        assert_eq!(editor.current_index(), 2);
+        editor
+            .open(dir.join(".").join("b.txt").to_string_lossy().into_owned())
+            .unwrap();
+        assert_eq!(editor.current_index(), 1);
+        assert_eq!(editor.buffer().cursor.x, 0);
+        assert_eq!(editor.first_modified(), Some(1));
+        assert_eq!(
+            editor.list(),
+            format!(
+                "1 {}, >*2 {}, *3 {}",
+                path("a.txt"),
+                path("b.txt"),
+                path("c.txt")
+            )
+        );
+
+        editor.close_current().unwrap();
+        assert_eq!(editor.documents().len(), 2);
+        assert_eq!(editor.current().name(), path("c.txt"));
+        editor.close_current().unwrap();
+        editor.close_current().unwrap();
+        assert_eq!(editor.documents().len(), 1);
+        assert_eq!(editor.current().name(), "untitled");
+    }
+
+    #[test]
+    fn test_editor_views() {
+        let mut editor = Editor::new();
+        editor.buffer_mut().insert_text("one\ntwo");
+        editor.split(Split::Vertical);
+        assert_eq!(editor.layout().panes(), [0, 1]);
+        assert_eq!(editor.focused(), 1);
+        editor.buffer_mut().set_cursor_offset(0);
+
+        // Both look at the same buffer from different places.
+        editor.buffer_mut().insert_text("zero\n");
+        assert_eq!(editor.view_cursor(1).y, 1);
+        assert_eq!(editor.view_cursor(0).y, 2);
+        editor.sync_views();
+        editor.focus_next();
+        assert_eq!(editor.focused(), 0);
+        assert_eq!(editor.buffer().cursor_offset(), 12);
+        assert_eq!(editor.view_cursor(1).y, 1);
+
+        editor.split(Split::Horizontal);
+        editor.documents.push(Document::new(Buffer::new()));
+        editor.next();
+        assert_eq!(editor.views()[2].document, 1);
+        editor.close_current().unwrap();
+        assert_eq!(editor.views()[2].document, 0);
+
+        editor.focus(1);
+        editor.close_pane();
+        assert_eq!(editor.views().len(), 2);
+        // The one below the first took the place of the one that was closed.
+        assert_eq!(editor.layout().panes(), [0, 1]);
+        assert_eq!(editor.focused(), 0);
+        assert_eq!(editor.buffer().cursor_offset(), 12);
+    }
+}
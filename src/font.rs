@@ -0,0 +1,650 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::config;
+use crate::image::Image;
+use crate::v2;
+use crate::vector::Vector2;
+
+// Where a glyph is in the atlas and where it goes in its cell,
+// in pixels from the top left.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Glyph {
+    pub pos: Vector2<i32>,
+    pub size: Vector2<i32>,
+    pub offset: Vector2<i32>,
+}
+
+const ATLAS_INITIAL_SIZE: i32 = 256;
+// Unless the GPU can't take that much, see `max_size`.
+const ATLAS_MAX_SIZE: i32 = 4096;
+// Between glyphs, so none of them bleeds into the next.
+const ATLAS_PADDING: i32 = 1;
+
+// Coverage of every glyph put in so far, packed in rows as they come.
+// It grows when there's no room left, without moving what's there.
+pub struct Atlas {
+    pub pixels: Vec<u8>,
+    pub size: Vector2<i32>,
+    // Where the next glyph goes, and how tall the row it's on is so far.
+    cursor: Vector2<i32>,
+    row_height: i32,
+    // Changed since it was last sent to the GPU.
+    pub dirty: bool,
+    // How big it's allowed to grow, each way.
+    pub max_size: i32,
+}
+
+impl Default for Atlas {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Atlas {
+    pub fn new() -> Self {
+        let size = v2!(ATLAS_INITIAL_SIZE, ATLAS_INITIAL_SIZE);
+        Self {
+            pixels: vec![0; (size.x * size.y) as usize],
+            size,
+            cursor: v2!(0, 0),
+            row_height: 0,
+            dirty: true,
+            max_size: ATLAS_MAX_SIZE,
+        }
+    }
+
+    // Copies in `coverage`, `size.x` pixels to a row, and tells where it went,
+    // or None when it's full.
+    pub fn add(&mut self, size: Vector2<i32>, coverage: &[u8]) -> Option<Vector2<i32>> {
+        let mut pos = self.cursor;
+        let mut row_height = self.row_height;
+        if pos.x + size.x > self.size.x {
+            pos = v2!(0, pos.y + row_height + ATLAS_PADDING);
+            row_height = 0;
+        }
+        while size.x > self.size.x || pos.y + size.y > self.size.y {
+            if self.size.x * 2 > self.max_size {
+                return None;
+            }
+            self.grow();
+        }
+        for y in 0..size.y {
+            let src = (y * size.x) as usize;
+            let dst = ((pos.y + y) * self.size.x + pos.x) as usize;
+            self.pixels[dst..dst + size.x as usize]
+                .copy_from_slice(&coverage[src..src + size.x as usize]);
+        }
+        self.cursor = v2!(pos.x + size.x + ATLAS_PADDING, pos.y);
+        self.row_height = row_height.max(size.y);
+        self.dirty = true;
+        Some(pos)
+    }
+
+    // Twice as big each way, with what's there staying at the top left.
+    fn grow(&mut self) {
+        let size = v2!(self.size.x * 2, self.size.y * 2);
+        let mut pixels = vec![0; (size.x * size.y) as usize];
+        for (y, row) in self.pixels.chunks(self.size.x as usize).enumerate() {
+            let dst = y * size.x as usize;
+            pixels[dst..dst + row.len()].copy_from_slice(row);
+        }
+        self.pixels = pixels;
+        self.size = size;
+    }
+}
+
+enum Source {
+    // Every glyph went in the atlas when it was loaded.
+    Bitmap,
+    // Glyphs are rasterized the first time they're drawn.
+    TrueType {
+        font: fontdue::Font,
+        px: f32,
+        ascent: i32,
+    },
+}
+
+// A monospaced font, every glyph taking a cell of the same size.
+pub struct Font {
+    source: Source,
+    // In font pixels.
+    pub cell: Vector2<i32>,
+    // How much bigger than its pixels it's drawn.
+    pub scale: f32,
+    pub atlas: Atlas,
+    // None for chars the font has no glyph for.
+    glyphs: HashMap<char, Option<Glyph>>,
+}
+
+impl Font {
+    pub fn load(config: &config::Font) -> Result<Self, String> {
+        if config.file.is_empty() {
+            return Self::load_grid(&config.atlas, config.columns, config.rows, config.scale);
+        }
+        let extension = Path::new(&config.file)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("ttf" | "otf") => Self::load_truetype(&config.file, config.size),
+            Some("bdf") => Self::load_bdf(&config.file, config.scale),
+            Some("psf" | "psfu") => Self::load_psf(&config.file, config.scale),
+            _ => Err(format!(
+                "{}: unknown kind of font, expected .ttf, .otf, .bdf or .psf",
+                config.file
+            )),
+        }
+    }
+
+    // An image with a grid of `columns` by `rows` glyphs, printable ASCII
+    // from the top left starting at space.
+    pub fn load_grid(path: &str, columns: u32, rows: u32, scale: f32) -> Result<Self, String> {
+        let image = Image::load(path)?;
+        let cell = v2!(image.width / columns as i32, image.height / rows as i32);
+        if cell.x == 0 || cell.y == 0 {
+            return Err(format!(
+                "{}: {}x{} is too small for {} columns and {} rows",
+                path, image.width, image.height, columns, rows
+            ));
+        }
+        let mut font = Self::new(Source::Bitmap, cell, scale);
+        let mut coverage = vec![0; (cell.x * cell.y) as usize];
+        for (i, ch) in (' '..='~').enumerate().take((columns * rows) as usize) {
+            let corner = v2!(
+                i as i32 % columns as i32 * cell.x,
+                i as i32 / columns as i32 * cell.y
+            );
+            for y in 0..cell.y {
+                for x in 0..cell.x {
+                    let pixel = ((corner.y + y) * image.width + corner.x + x) as usize;
+                    // White on whatever, the red channel says it all.
+                    coverage[(y * cell.x + x) as usize] = image.pixels[pixel * 4];
+                }
+            }
+            font.insert(ch, cell, v2!(0, 0), &coverage);
+        }
+        Ok(font)
+    }
+
+    // TrueType or OpenType, rasterized `size` pixels tall.
+    pub fn load_truetype(path: &str, size: f32) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|err| format!("{}: {}", path, err))?;
+        let font = fontdue::Font::from_bytes(bytes, fontdue::FontSettings::default())
+            .map_err(|err| format!("{}: {}", path, err))?;
+        let line = font
+            .horizontal_line_metrics(size)
+            .ok_or_else(|| format!("{}: no horizontal metrics", path))?;
+        let cell = v2!(
+            font.metrics('M', size).advance_width.round() as i32,
+            line.new_line_size.round() as i32,
+        );
+        let source = Source::TrueType {
+            font,
+            px: size,
+            ascent: line.ascent.round() as i32,
+        };
+        Ok(Self::new(source, cell, 1.0))
+    }
+
+    // Glyph Bitmap Distribution Format, what X11 bitmap fonts come as.
+    pub fn load_bdf(path: &str, scale: f32) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+        Self::parse_bdf(&text, scale).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    fn parse_bdf(text: &str, scale: f32) -> Result<Self, String> {
+        // Width, height and offset from the origin, y going up.
+        let mut bounds = None;
+        let mut ascent = None;
+        let mut descent = None;
+        let mut registry = String::new();
+        let mut encoding = String::new();
+        let mut glyphs = vec![];
+        let mut lines = text.lines().enumerate().map(|(i, line)| (i + 1, line));
+        while let Some((n, line)) = lines.next() {
+            let mut words = line.split_whitespace();
+            let at = |err: String| format!("line {}: {}", n, err);
+            match words.next() {
+                Some("FONTBOUNDINGBOX") => bounds = Some(bdf_numbers::<4>(words).map_err(at)?),
+                Some("FONT_ASCENT") => ascent = Some(bdf_numbers::<1>(words).map_err(at)?[0]),
+                Some("FONT_DESCENT") => descent = Some(bdf_numbers::<1>(words).map_err(at)?[0]),
+                Some("CHARSET_REGISTRY") => registry = bdf_string(words),
+                Some("CHARSET_ENCODING") => encoding = bdf_string(words),
+                Some("STARTCHAR") => glyphs.push(bdf_glyph(n, &mut lines)?),
+                _ => {}
+            }
+        }
+        let [width, height, x, y] = bounds.ok_or("no FONTBOUNDINGBOX")?;
+        if !(1..=1024).contains(&width) || !(1..=1024).contains(&height) {
+            return Err(format!("{}x{} isn't a glyph size", width, height));
+        }
+        let ascent = ascent.unwrap_or(height + y);
+        let cell = v2!(width, descent.map_or(height, |descent| ascent + descent));
+        if cell.x < 1 || cell.y < 1 {
+            return Err(format!("{}x{} is too small for a cell", cell.x, cell.y));
+        }
+        // Other charsets agree with Unicode on ASCII at least.
+        let unicode = matches!(
+            (registry.as_str(), encoding.as_str()),
+            ("" | "ISO10646", _) | ("ISO8859", "1")
+        );
+        let mut font = Self::new(Source::Bitmap, cell, scale);
+        for (code, [w, h, xoff, yoff], bits) in glyphs {
+            let ch = match char::from_u32(code) {
+                Some(ch) if unicode || ch.is_ascii() => ch,
+                _ => continue,
+            };
+            let size = v2!(w, h);
+            let offset = v2!(xoff - x, ascent - yoff - h);
+            font.insert(ch, size, offset, &unpack_bits(&bits, size));
+        }
+        Ok(font)
+    }
+
+    // PC Screen Font, version 1 or 2, what the Linux console uses.
+    pub fn load_psf(path: &str, scale: f32) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|err| format!("{}: {}", path, err))?;
+        Self::parse_psf(&bytes, scale).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    fn parse_psf(bytes: &[u8], scale: f32) -> Result<Self, String> {
+        let (cell, count, glyph_len, glyphs_at, table) = match bytes {
+            [0x36, 0x04, mode, height, ..] => {
+                let count = if mode & 0x01 != 0 { 512 } else { 256 };
+                let table = (mode & 0x06 != 0).then_some(UnicodeTable::Psf1);
+                (v2!(8, *height as i32), count, *height as usize, 4, table)
+            }
+            [0x72, 0xb5, 0x4a, 0x86, ..] if bytes.len() >= 32 => {
+                let word = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+                let (width, height) = (word(28).min(1024), word(24).min(1024));
+                let table = (word(12) & 0x01 != 0).then_some(UnicodeTable::Psf2);
+                if (word(20) as usize) < (width as usize).div_ceil(8) * height as usize {
+                    return Err("glyphs are smaller than their size".into());
+                }
+                let cell = v2!(width as i32, height as i32);
+                (
+                    cell,
+                    word(16) as usize,
+                    word(20) as usize,
+                    word(8) as usize,
+                    table,
+                )
+            }
+            _ => return Err("not a PSF font".into()),
+        };
+        if cell.x == 0 || cell.y == 0 {
+            return Err("glyphs are empty".into());
+        }
+        let glyphs_end = count
+            .checked_mul(glyph_len)
+            .and_then(|len| len.checked_add(glyphs_at))
+            .filter(|&end| end <= bytes.len())
+            .ok_or("truncated")?;
+        let glyphs = &bytes[glyphs_at..glyphs_end];
+        let chars = match table {
+            Some(table) => table.parse(&bytes[glyphs_end..], count)?,
+            // Without a table, glyphs go in the order of their code points.
+            None => (0..count as u32)
+                .map(|i| char::from_u32(i).into_iter().collect())
+                .collect(),
+        };
+        let mut font = Self::new(Source::Bitmap, cell, scale);
+        for (bits, chars) in glyphs.chunks(glyph_len).zip(chars) {
+            if chars.is_empty() {
+                continue;
+            }
+            let Some(pos) = font.atlas.add(cell, &unpack_bits(bits, cell)) else {
+                break;
+            };
+            let glyph = Glyph {
+                pos,
+                size: cell,
+                offset: v2!(0, 0),
+            };
+            // The first glyph a char is mapped to wins.
+            for ch in chars {
+                font.glyphs.entry(ch).or_insert(Some(glyph));
+            }
+        }
+        Ok(font)
+    }
+
+    fn new(source: Source, cell: Vector2<i32>, scale: f32) -> Self {
+        Self {
+            source,
+            cell,
+            scale,
+            atlas: Atlas::new(),
+            glyphs: HashMap::new(),
+        }
+    }
+
+    // For glyphs that come already rasterized, `offset` into the cell.
+    pub fn insert(&mut self, ch: char, size: Vector2<i32>, offset: Vector2<i32>, coverage: &[u8]) {
+        if let Some(pos) = self.atlas.add(size, coverage) {
+            self.glyphs.insert(ch, Some(Glyph { pos, size, offset }));
+        }
+    }
+
+    // What's missing, or didn't fit in the atlas, is drawn as a '?',
+    // or not at all without one.
+    pub fn glyph(&mut self, ch: char) -> Glyph {
+        self.lookup(ch)
+            .or_else(|| self.lookup('?'))
+            .unwrap_or_default()
+    }
+
+    fn lookup(&mut self, ch: char) -> Option<Glyph> {
+        if let Some(glyph) = self.glyphs.get(&ch) {
+            return *glyph;
+        }
+        let glyph = match &self.source {
+            Source::Bitmap => None,
+            Source::TrueType { font, .. } if font.lookup_glyph_index(ch) == 0 => None,
+            Source::TrueType { font, px, ascent } => {
+                let (metrics, coverage) = font.rasterize(ch, *px);
+                let size = v2!(metrics.width as i32, metrics.height as i32);
+                // Metrics go up from the baseline, cells down from the top.
+                let offset = v2!(metrics.xmin, ascent - metrics.ymin - size.y);
+                let pos = self.atlas.add(size, &coverage);
+                pos.map(|pos| Glyph { pos, size, offset })
+            }
+        };
+        self.glyphs.insert(ch, glyph);
+        glyph
+    }
+}
+
+// One bit a pixel, leftmost in the highest bit, rows taking whole bytes.
+fn unpack_bits(bits: &[u8], size: Vector2<i32>) -> Vec<u8> {
+    let stride = (size.x as usize).div_ceil(8);
+    let mut coverage = vec![0; (size.x * size.y) as usize];
+    for (y, row) in bits.chunks(stride).take(size.y as usize).enumerate() {
+        for x in 0..size.x as usize {
+            if row
+                .get(x / 8)
+                .is_some_and(|byte| byte & (0x80 >> (x % 8)) != 0)
+            {
+                coverage[y * size.x as usize + x] = 255;
+            }
+        }
+    }
+    coverage
+}
+
+fn bdf_numbers<'a, const N: usize>(
+    mut words: impl Iterator<Item = &'a str>,
+) -> Result<[i32; N], String> {
+    let mut numbers = [0; N];
+    for number in &mut numbers {
+        let word = words.next().ok_or("missing a number")?;
+        *number = word
+            .parse()
+            .map_err(|_| format!("`{}` isn't a number", word))?;
+    }
+    Ok(numbers)
+}
+
+fn bdf_string<'a>(words: impl Iterator<Item = &'a str>) -> String {
+    let words: Vec<_> = words.collect();
+    words.join(" ").trim_matches('"').to_ascii_uppercase()
+}
+
+// What's between STARTCHAR and ENDCHAR: the code point, the bounding box
+// like FONTBOUNDINGBOX, and the bitmap.
+fn bdf_glyph<'a>(
+    start: usize,
+    lines: &mut impl Iterator<Item = (usize, &'a str)>,
+) -> Result<(u32, [i32; 4], Vec<u8>), String> {
+    let mut code = None;
+    let mut bounds = None;
+    let mut bits = vec![];
+    let mut bitmap = false;
+    for (n, line) in lines {
+        let mut words = line.split_whitespace();
+        let at = |err: String| format!("line {}: {}", n, err);
+        match words.next() {
+            Some("ENDCHAR") => {
+                let bounds = bounds.ok_or_else(|| at("glyph without BBX".into()))?;
+                // Unencoded glyphs are -1, there's no char for them.
+                return Ok((code.unwrap_or(u32::MAX), bounds, bits));
+            }
+            Some(row) if bitmap => {
+                let [w, _, _, _] = bounds.ok_or_else(|| at("BITMAP before BBX".into()))?;
+                // Rows can have more bytes than the glyph is wide.
+                for i in 0..(w as usize).div_ceil(8) {
+                    let byte = row
+                        .get(i * 2..i * 2 + 2)
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                        .ok_or_else(|| at(format!("`{}` isn't a row {} pixels wide", row, w)))?;
+                    bits.push(byte);
+                }
+            }
+            Some("ENCODING") => {
+                code = Some(bdf_numbers::<1>(words).map_err(at)?[0] as u32);
+            }
+            Some("BBX") => {
+                let bbx = bdf_numbers::<4>(words).map_err(at)?;
+                if !(0..=1024).contains(&bbx[0]) || !(0..=1024).contains(&bbx[1]) {
+                    return Err(at(format!("{}x{} isn't a glyph size", bbx[0], bbx[1])));
+                }
+                bounds = Some(bbx);
+            }
+            Some("BITMAP") => bitmap = true,
+            _ => {}
+        }
+    }
+    Err(format!("line {}: STARTCHAR without ENDCHAR", start))
+}
+
+// Which chars each glyph of a PSF font is for.
+#[derive(Clone, Copy)]
+enum UnicodeTable {
+    // 16 bit code points, 0xffff after each glyph's.
+    Psf1,
+    // UTF-8, 0xff after each glyph's.
+    Psf2,
+}
+
+impl UnicodeTable {
+    // Sequences of chars drawn as a single glyph, after 0xfffe or 0xfe,
+    // are left out.
+    fn parse(self, bytes: &[u8], count: usize) -> Result<Vec<Vec<char>>, String> {
+        let mut chars = vec![];
+        match self {
+            UnicodeTable::Psf1 => {
+                let mut codes = bytes
+                    .chunks_exact(2)
+                    .map(|pair| u16::from_le_bytes([pair[0], pair[1]]));
+                while chars.len() < count {
+                    let mut glyph = vec![];
+                    let mut sequences = false;
+                    loop {
+                        match codes.next().ok_or("truncated unicode table")? {
+                            0xffff => break,
+                            0xfffe => sequences = true,
+                            code if !sequences => glyph.extend(char::from_u32(code as u32)),
+                            _ => {}
+                        }
+                    }
+                    chars.push(glyph);
+                }
+            }
+            UnicodeTable::Psf2 => {
+                let mut entries = bytes.split(|&byte| byte == 0xff);
+                while chars.len() < count {
+                    let entry = entries.next().ok_or("truncated unicode table")?;
+                    let single = entry.split(|&byte| byte == 0xfe).next().unwrap_or_default();
+                    let text = std::str::from_utf8(single)
+                        .map_err(|_| "unicode table isn't UTF-8".to_string())?;
+                    chars.push(text.chars().collect());
+                }
+            }
+        }
+        Ok(chars)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_atlas() {
+        let mut atlas = Atlas::new();
+        let glyph = v2!(100, 60);
+        let a = atlas.add(glyph, &[1; 6000]).unwrap();
+        let b = atlas.add(glyph, &[2; 6000]).unwrap();
+        let c = atlas.add(glyph, &[3; 6000]).unwrap();
+        assert_eq!((a, b, c), (v2!(0, 0), v2!(101, 0), v2!(0, 61)));
+        for _ in 0..6 {
+            atlas.add(glyph, &[4; 6000]);
+        }
+        // Whatever was there stays where it was.
+        assert_eq!(atlas.size, v2!(512, 512));
+        assert_eq!(atlas.pixels[(b.y * atlas.size.x + b.x) as usize], 2);
+        assert_eq!(
+            atlas.pixels[((c.y + 59) * atlas.size.x + c.x + 99) as usize],
+            3
+        );
+        assert_eq!(atlas.add(v2!(600, 1), &[5; 600]), Some(v2!(0, 305)));
+        assert_eq!(atlas.size, v2!(1024, 1024));
+
+        // It's only so big, though what fits still goes in.
+        atlas.max_size = 1024;
+        assert_eq!(atlas.add(v2!(1025, 1), &[6; 1025]), None);
+        assert_eq!(atlas.add(v2!(1, 1), &[7]), Some(v2!(601, 305)));
+        assert_eq!(atlas.size, v2!(1024, 1024));
+    }
+
+    #[test]
+    fn test_truetype_font() {
+        // Boxes for '?', 'A' and 'g', at 1000 units to the em, 800 of them
+        // above the baseline, each 600 wide.
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/fonts/fixtures/boxes.ttf");
+        let mut font = Font::load_truetype(path, 10.0).unwrap();
+        assert_eq!(font.cell, v2!(6, 10));
+        // Nothing gets rasterized until it's needed.
+        assert!(font.glyphs.is_empty());
+        let a = font.glyph('A');
+        assert_eq!((a.size, a.offset), (v2!(4, 7), v2!(1, 1)));
+        assert_eq!(
+            font.atlas.pixels[(a.pos.y * font.atlas.size.x + a.pos.x) as usize],
+            255
+        );
+        // Down to 2 pixels under the baseline.
+        let g = font.glyph('g');
+        assert_eq!((g.size, g.offset), (v2!(4, 7), v2!(1, 3)));
+        assert_eq!(font.glyph('A'), a);
+        assert_eq!(font.glyph('z'), font.glyph('?'));
+        assert_eq!(font.glyphs.len(), 4);
+
+        // Whatever doesn't fit in the atlas is a '?' too.
+        let mut font = Font::load_truetype(path, 400.0).unwrap();
+        font.atlas.max_size = font.atlas.size.x;
+        assert_eq!(font.glyph('A'), font.glyph('?'));
+        assert_ne!(font.glyph('?'), Glyph::default());
+    }
+
+    #[test]
+    fn test_grid_font() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/charmap-oldschool_white.png");
+        let mut font = Font::load_grid(path, 18, 7, 3.0).unwrap();
+        assert_eq!(font.cell, v2!(7, 9));
+        let a = font.glyph('A');
+        assert_eq!((a.size, a.offset), (font.cell, v2!(0, 0)));
+        assert_ne!(a, font.glyph('B'));
+        assert_eq!(font.glyph('\u{e9}'), font.glyph('?'));
+        assert!(Font::load_grid(path, 1000, 7, 3.0).is_err());
+    }
+
+    #[test]
+    fn test_bdf_font() {
+        let bdf = "STARTFONT 2.1
+FONTBOUNDINGBOX 4 6 0 -2
+STARTPROPERTIES 2
+FONT_ASCENT 4
+FONT_DESCENT 2
+ENDPROPERTIES
+CHARS 2
+STARTCHAR A
+ENCODING 65
+BBX 3 4 0 0
+BITMAP
+40
+A0
+E0
+A0
+ENDCHAR
+STARTCHAR unknown
+ENCODING -1
+BBX 1 1 0 0
+BITMAP
+80
+ENDCHAR
+ENDFONT
+";
+        let mut font = Font::parse_bdf(bdf, 2.0).unwrap();
+        assert_eq!(font.cell, v2!(4, 6));
+        let a = font.glyph('A');
+        assert_eq!((a.size, a.offset), (v2!(3, 4), v2!(0, 0)));
+        let row = |y: i32| {
+            let start = ((a.pos.y + y) * font.atlas.size.x + a.pos.x) as usize;
+            font.atlas.pixels[start..start + 3].to_vec()
+        };
+        assert_eq!(row(0), [0, 255, 0]);
+        assert_eq!(row(2), [255, 255, 255]);
+        // Nothing for '?' to fall back on.
+        assert_eq!(font.glyph('B'), Glyph::default());
+        assert_eq!(
+            Font::parse_bdf(&bdf.replace("E0", "G0"), 2.0)
+                .err()
+                .unwrap(),
+            "line 14: `G0` isn't a row 3 pixels wide"
+        );
+        let flat = bdf.replace("FONT_ASCENT 4", "FONT_ASCENT -2");
+        assert_eq!(
+            Font::parse_bdf(&flat, 2.0).err().unwrap(),
+            "4x0 is too small for a cell"
+        );
+    }
+
+    #[test]
+    fn test_psf_font() {
+        // Two 8x2 glyphs, the first for 'a' and 'b', the second for '\u{e9}'.
+        // PSF1 always has 256 of them, the rest are left empty.
+        let mut psf1 = vec![0x36, 0x04, 0x02, 2, 0xff, 0x00, 0x00, 0xff];
+        psf1.resize(4 + 256 * 2, 0);
+        psf1.extend_from_slice(&[b'a', 0, b'b', 0, 0xfe, 0xff, b'x', 0, 0xff, 0xff]);
+        psf1.extend_from_slice(&[0xe9, 0, 0xff, 0xff]);
+        psf1.extend(std::iter::repeat_n([0xff, 0xff], 254).flatten());
+
+        let mut psf2 = vec![0x72, 0xb5, 0x4a, 0x86];
+        for word in [0, 32, 1, 2, 2, 2, 8] {
+            psf2.extend_from_slice(&u32::to_le_bytes(word));
+        }
+        psf2.extend_from_slice(&[0xff, 0x00, 0x00, 0xff]);
+        psf2.extend_from_slice(b"ab\xfex\xff\xc3\xa9\xff");
+
+        for bytes in [psf1, psf2] {
+            let mut font = Font::parse_psf(&bytes, 1.0).unwrap();
+            assert_eq!(font.cell, v2!(8, 2));
+            let (a, e) = (font.glyph('a'), font.glyph('\u{e9}'));
+            assert_eq!(a, font.glyph('b'));
+            assert_ne!(a, e);
+            // 'x' only comes after 'a' in a sequence.
+            assert_eq!(font.glyph('x'), Glyph::default());
+            let pixel = |glyph: Glyph, y: i32| {
+                font.atlas.pixels[((glyph.pos.y + y) * font.atlas.size.x + glyph.pos.x) as usize]
+            };
+            assert_eq!((pixel(a, 0), pixel(a, 1)), (255, 0));
+            assert_eq!((pixel(e, 0), pixel(e, 1)), (0, 255));
+        }
+        assert_eq!(
+            Font::parse_psf(b"not a font", 1.0).err().unwrap(),
+            "not a PSF font"
+        );
+    }
+}
@@ -0,0 +1,444 @@
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::config::{self, Fields};
+use crate::highlight::{State, Token, TokenKind};
+use crate::regex::Regex;
+use crate::toml;
+
+const BUILTIN: &[(&str, &str)] = &[
+    ("c", include_str!("../grammars/c.toml")),
+    ("markdown", include_str!("../grammars/markdown.toml")),
+    ("rust", include_str!("../grammars/rust.toml")),
+    ("shell", include_str!("../grammars/shell.toml")),
+    ("toml", include_str!("../grammars/toml.toml")),
+];
+
+// Opening and closing quotes of a string and whether backslash escapes in between.
+#[derive(Debug)]
+struct Delimiters {
+    open: String,
+    close: String,
+    escapes: bool,
+}
+
+// How to tell the parts of a language apart, as a grammar file describes it.
+// At each spot of a line the first of these that fits wins: `patterns`,
+// comments, `strings`, numbers, then identifiers that are keywords or types.
+#[derive(Debug)]
+pub struct Grammar {
+    pub name: String,
+    extensions: Vec<String>,
+    // Programs in the shebang of scripts with no extension.
+    shebangs: Vec<String>,
+    keywords: HashSet<String>,
+    types: HashSet<String>,
+    line_comment: String,
+    block_comment: Option<(String, String)>,
+    nested_comments: bool,
+    // Longer quotes go first, the first one that matches is taken.
+    strings: Vec<Delimiters>,
+    number: Option<Regex>,
+    identifier: Option<Regex>,
+    patterns: Vec<(Regex, TokenKind)>,
+}
+
+fn is_word(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+fn char_len(line: &str, i: usize) -> usize {
+    line[i..].chars().next().map_or(1, char::len_utf8)
+}
+
+// How much of `line` at `at` the regex matches, if anything.
+fn match_len(regex: Option<&Regex>, line: &str, at: usize) -> Option<usize> {
+    let range = regex?.match_at(line.into(), at)?.range();
+    Some(range.len()).filter(|&len| len > 0)
+}
+
+fn regex(fields: &mut Fields, key: &str) -> Option<Regex> {
+    let mut pattern = String::new();
+    fields.string(key, &mut pattern);
+    if pattern.is_empty() {
+        return None;
+    }
+    Regex::new(&pattern)
+        .map_err(|err| fields.error(key, err))
+        .ok()
+}
+
+impl Grammar {
+    // Everything that's wrong with it is reported at once, one per line.
+    pub fn parse(name: &str, text: &str) -> Result<Self, String> {
+        let table = toml::parse(text)?;
+        let mut grammar = Grammar {
+            name: name.to_string(),
+            extensions: vec![],
+            shebangs: vec![],
+            keywords: HashSet::new(),
+            types: HashSet::new(),
+            line_comment: String::new(),
+            block_comment: None,
+            nested_comments: false,
+            strings: vec![],
+            number: None,
+            identifier: None,
+            patterns: vec![],
+        };
+        let mut errors = vec![];
+        let mut fields = Fields {
+            table: &table,
+            prefix: "",
+            errors: &mut errors,
+        };
+        fields.strings("extensions", &mut grammar.extensions);
+        fields.strings("shebangs", &mut grammar.shebangs);
+        let mut words = vec![];
+        fields.strings("keywords", &mut words);
+        grammar.keywords = words.drain(..).collect();
+        fields.strings("types", &mut words);
+        grammar.types = words.drain(..).collect();
+        fields.string("line_comment", &mut grammar.line_comment);
+        grammar.number = regex(&mut fields, "number");
+        grammar.identifier = regex(&mut fields, "identifier");
+        fields.table("block_comment", |fields| {
+            let (mut open, mut close) = (String::new(), String::new());
+            fields.string("open", &mut open);
+            fields.string("close", &mut close);
+            fields.boolean("nested", &mut grammar.nested_comments);
+            fields.unknown(&["open", "close", "nested"]);
+            if open.is_empty() || close.is_empty() {
+                fields.error("open", "both open and close are needed".into());
+            } else {
+                grammar.block_comment = Some((open, close));
+            }
+        });
+        fields.tables("strings", |fields| {
+            let mut delimiters = Delimiters {
+                open: String::new(),
+                close: String::new(),
+                escapes: true,
+            };
+            fields.string("open", &mut delimiters.open);
+            fields.string("close", &mut delimiters.close);
+            fields.boolean("escapes", &mut delimiters.escapes);
+            fields.unknown(&["open", "close", "escapes"]);
+            if delimiters.open.is_empty() || delimiters.close.is_empty() {
+                fields.error("open", "both open and close are needed".into());
+            } else {
+                grammar.strings.push(delimiters);
+            }
+        });
+        fields.tables("patterns", |fields| {
+            let regex = regex(fields, "regex");
+            let mut kind = String::new();
+            fields.string("kind", &mut kind);
+            fields.unknown(&["regex", "kind"]);
+            match (regex, TokenKind::from_name(&kind)) {
+                (Some(regex), Some(kind)) => grammar.patterns.push((regex, kind)),
+                (None, _) => fields.error("regex", "missing".into()),
+                (_, None) => fields.error("kind", format!("`{}` isn't a kind of token", kind)),
+            }
+        });
+        fields.unknown(&[
+            "extensions",
+            "shebangs",
+            "keywords",
+            "types",
+            "line_comment",
+            "block_comment",
+            "number",
+            "identifier",
+            "strings",
+            "patterns",
+        ]);
+        if errors.is_empty() {
+            Ok(grammar)
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+
+    // The tokens on `line` and the state it leaves the next line in.
+    pub fn lex(&self, line: &str, state: &State) -> (Vec<Token>, State) {
+        let mut tokens = vec![];
+        let mut state = state.clone();
+        let mut i = 0;
+        while i < line.len() {
+            let start = i;
+            let rest = &line[i..];
+            let after_word = line[..i].chars().next_back().is_some_and(is_word);
+            let kind = match state {
+                State::Comment(depth) => {
+                    i = self.skip_comment(line, i, depth, &mut state);
+                    TokenKind::Comment
+                }
+                State::String(n) => {
+                    i = self.skip_string(line, i, n, &mut state);
+                    TokenKind::String
+                }
+                State::Normal => {
+                    let pattern = self
+                        .patterns
+                        .iter()
+                        .find_map(|(regex, kind)| Some((match_len(Some(regex), line, i)?, *kind)));
+                    let string = self.strings.iter().position(|delimiters| {
+                        rest.starts_with(&delimiters.open)
+                            && !(after_word && delimiters.open.starts_with(is_word))
+                    });
+                    if let Some((len, kind)) = pattern {
+                        i += len;
+                        kind
+                    } else if let Some((open, _)) = self
+                        .block_comment
+                        .as_ref()
+                        .filter(|(open, _)| rest.starts_with(open.as_str()))
+                    {
+                        i = self.skip_comment(line, i + open.len(), 1, &mut state);
+                        TokenKind::Comment
+                    } else if !self.line_comment.is_empty()
+                        && !after_word
+                        && rest.starts_with(&self.line_comment)
+                    {
+                        i = line.len();
+                        TokenKind::Comment
+                    } else if let Some(n) = string {
+                        let open = self.strings[n].open.len();
+                        i = self.skip_string(line, i + open, n, &mut state);
+                        TokenKind::String
+                    } else if let Some(len) =
+                        match_len(self.number.as_ref(), line, i).filter(|_| !after_word)
+                    {
+                        i += len;
+                        TokenKind::Number
+                    } else if let Some(len) = match_len(self.identifier.as_ref(), line, i) {
+                        i += len;
+                        let word = &line[start..i];
+                        if self.keywords.contains(word) {
+                            TokenKind::Keyword
+                        } else if self.types.contains(word) {
+                            TokenKind::Type
+                        } else {
+                            continue;
+                        }
+                    } else {
+                        i += char_len(line, i);
+                        continue;
+                    }
+                }
+            };
+            tokens.push(Token {
+                range: start..i,
+                kind,
+            });
+        }
+        (tokens, state)
+    }
+
+    // Where the block comment `depth` deep at `i` ends, or the end of the
+    // line with `state` saying how deep it still is.
+    fn skip_comment(&self, line: &str, mut i: usize, mut depth: usize, state: &mut State) -> usize {
+        let Some((open, close)) = &self.block_comment else {
+            return line.len();
+        };
+        while i < line.len() {
+            if line[i..].starts_with(close.as_str()) {
+                i += close.len();
+                depth -= 1;
+                if depth == 0 {
+                    break;
+                }
+            } else if self.nested_comments && line[i..].starts_with(open.as_str()) {
+                i += open.len();
+                depth += 1;
+            } else {
+                i += char_len(line, i);
+            }
+        }
+        *state = match depth {
+            0 => State::Normal,
+            _ => State::Comment(depth),
+        };
+        i
+    }
+
+    // Same for the string `n` that's open at `i`.
+    fn skip_string(&self, line: &str, mut i: usize, n: usize, state: &mut State) -> usize {
+        let Delimiters { close, escapes, .. } = &self.strings[n];
+        *state = State::String(n);
+        while i < line.len() {
+            if line[i..].starts_with(close.as_str()) {
+                *state = State::Normal;
+                return i + close.len();
+            }
+            if *escapes && line[i..].starts_with('\\') {
+                i += 1;
+                if i == line.len() {
+                    break;
+                }
+            }
+            i += char_len(line, i);
+        }
+        i
+    }
+}
+
+// Every grammar there is, built-in or from the config directory.
+pub struct Grammars {
+    grammars: Vec<Grammar>,
+}
+
+impl Grammars {
+    pub fn builtin() -> Self {
+        let grammars = BUILTIN
+            .iter()
+            .map(|(name, text)| {
+                Grammar::parse(name, text).unwrap_or_else(|err| panic!("{}.toml: {}", name, err))
+            })
+            .collect();
+        Self { grammars }
+    }
+
+    // Adds the user's grammars, which take the place of built-in ones
+    // with the same name.
+    pub fn load_dir(&mut self, dir: &Path) -> Result<(), String> {
+        let (grammars, errors) = config::load_toml_dir(dir, Grammar::parse);
+        for grammar in grammars {
+            self.add(grammar);
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+
+    pub fn add(&mut self, grammar: Grammar) {
+        match self.grammars.iter_mut().find(|g| g.name == grammar.name) {
+            Some(old) => *old = grammar,
+            None => self.grammars.push(grammar),
+        }
+    }
+
+    pub fn get(&self, i: usize) -> &Grammar {
+        &self.grammars[i]
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.grammars.iter().map(|grammar| grammar.name.as_str())
+    }
+
+    // By the extension of `path`, or the shebang on the first line for scripts.
+    pub fn detect(&self, path: &Path, first_line: &str) -> Option<usize> {
+        let ext = path.extension().and_then(|ext| ext.to_str());
+        let by_extension = self
+            .grammars
+            .iter()
+            .position(|grammar| grammar.extensions.iter().any(|e| Some(e.as_str()) == ext));
+        if by_extension.is_some() {
+            return by_extension;
+        }
+        let mut words = first_line.strip_prefix("#!")?.split_whitespace();
+        let mut program = words.next()?.rsplit('/').next()?;
+        if program == "env" {
+            program = words.next()?;
+        }
+        self.grammars
+            .iter()
+            .position(|grammar| grammar.shebangs.iter().any(|p| p == program))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn dump(grammar: &Grammar, text: &str) -> String {
+        let mut state = State::Normal;
+        let mut dump = String::new();
+        for (y, line) in text.lines().enumerate() {
+            let (tokens, end) = grammar.lex(line, &state);
+            for token in tokens {
+                let text = &line[token.range.clone()];
+                dump += &format!(
+                    "{}:{} {} {}\n",
+                    y + 1,
+                    token.range.start,
+                    token.kind.name(),
+                    text
+                );
+            }
+            state = end;
+        }
+        dump
+    }
+
+    // Each built-in grammar highlights its sample in `grammars/fixtures` the
+    // way the `.tokens` file next to it says. Set RED_BLESS=1 to rewrite those.
+    #[test]
+    fn test_builtin_grammars() {
+        let grammars = Grammars::builtin();
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("grammars/fixtures");
+        let mut samples: Vec<PathBuf> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_none_or(|ext| ext != "tokens"))
+            .collect();
+        samples.sort();
+        let mut tested = vec![];
+        for sample in samples {
+            let text = fs::read_to_string(&sample).unwrap();
+            let first_line = text.lines().next().unwrap_or_default();
+            let i = grammars.detect(&sample, first_line).unwrap();
+            let dump = dump(grammars.get(i), &text);
+            let expected = sample.with_file_name(format!(
+                "{}.tokens",
+                sample.file_name().unwrap().to_string_lossy()
+            ));
+            if std::env::var_os("RED_BLESS").is_some() {
+                fs::write(&expected, &dump).unwrap();
+            }
+            assert_eq!(
+                dump,
+                fs::read_to_string(&expected).unwrap(),
+                "{}",
+                sample.display()
+            );
+            tested.push(grammars.get(i).name.clone());
+        }
+        tested.sort();
+        tested.dedup();
+        assert_eq!(tested, grammars.names().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_grammar_errors() {
+        let mut grammars = Grammars::builtin();
+        let grammar =
+            Grammar::parse("ini", "extensions = [\"ini\"]\nline_comment = \";\"").unwrap();
+        grammars.add(grammar);
+        assert_eq!(grammars.detect(Path::new("a.ini"), ""), Some(5));
+        assert_eq!(grammars.detect(Path::new("a.rs"), ""), Some(2));
+        assert_eq!(
+            grammars.detect(Path::new("run"), "#!/usr/bin/env bash"),
+            Some(3)
+        );
+        assert_eq!(grammars.detect(Path::new("run"), "#!/usr/bin/python"), None);
+        assert_eq!(grammars.detect(Path::new("a.txt"), "#!/bin/sh"), Some(3));
+
+        assert_eq!(
+            Grammar::parse(
+                "x",
+                "keywords = [1]\nnumber = \"[0-9\"\n[[strings]]\nopen = \"'\"\n[[patterns]]\nregex = \"x\"\nkind = \"verb\""
+            )
+            .unwrap_err(),
+            "keywords: expected an array of strings\n\
+             number: regex error at 4: unexpected end of pattern\n\
+             strings[0].open: both open and close are needed\n\
+             patterns[0].kind: `verb` isn't a kind of token"
+        );
+    }
+}
@@ -0,0 +1,78 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineNumbers {
+    Absolute,
+    // How far each line is from the cursor's, which shows its own number.
+    Relative,
+    Off,
+}
+
+impl LineNumbers {
+    pub fn name(self) -> &'static str {
+        match self {
+            LineNumbers::Absolute => "absolute",
+            LineNumbers::Relative => "relative",
+            LineNumbers::Off => "off",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            LineNumbers::Absolute,
+            LineNumbers::Relative,
+            LineNumbers::Off,
+        ]
+        .into_iter()
+        .find(|line_numbers| line_numbers.name() == name)
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            LineNumbers::Absolute => LineNumbers::Relative,
+            LineNumbers::Relative => LineNumbers::Off,
+            LineNumbers::Off => LineNumbers::Absolute,
+        }
+    }
+
+    // Columns the gutter takes, as many as the last line number has digits
+    // with a space on either side.
+    pub fn width(self, line_count: usize) -> usize {
+        match self {
+            LineNumbers::Off => 0,
+            _ => line_count.max(1).to_string().len() + 2,
+        }
+    }
+
+    // What goes in the gutter next to line `y`, right aligned.
+    pub fn label(self, y: usize, cursor_y: usize, width: usize) -> String {
+        let n = match self {
+            LineNumbers::Off => return String::new(),
+            LineNumbers::Relative if y != cursor_y => y.abs_diff(cursor_y),
+            _ => y + 1,
+        };
+        format!("{:>1$} ", n, width.saturating_sub(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_numbers() {
+        let absolute = LineNumbers::Absolute;
+        assert_eq!(absolute.width(9), 3);
+        assert_eq!(absolute.width(10), 4);
+        assert_eq!(absolute.label(9, 0, 4), " 10 ");
+        assert_eq!(absolute.label(0, 0, 4), "  1 ");
+
+        let relative = LineNumbers::Relative;
+        assert_eq!(relative.label(3, 5, 4), "  2 ");
+        assert_eq!(relative.label(5, 5, 4), "  6 ");
+        assert_eq!(relative.label(7, 5, 4), "  2 ");
+
+        assert_eq!(LineNumbers::Off.width(1000), 0);
+        assert_eq!(LineNumbers::Off.label(1, 1, 0), "");
+        assert_eq!(LineNumbers::from_name("relative"), Some(relative));
+        assert_eq!(LineNumbers::Off.next(), absolute);
+    }
+}
@@ -0,0 +1,205 @@
+use std::ops::Range;
+
+use crate::buffer::Buffer;
+use crate::grammar::Grammars;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Keyword,
+    Type,
+    String,
+    Number,
+    Comment,
+    Heading,
+    Emphasis,
+    Variable,
+}
+
+impl TokenKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            TokenKind::Keyword => "keyword",
+            TokenKind::Type => "type",
+            TokenKind::String => "string",
+            TokenKind::Number => "number",
+            TokenKind::Comment => "comment",
+            TokenKind::Heading => "heading",
+            TokenKind::Emphasis => "emphasis",
+            TokenKind::Variable => "variable",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            TokenKind::Keyword,
+            TokenKind::Type,
+            TokenKind::String,
+            TokenKind::Number,
+            TokenKind::Comment,
+            TokenKind::Heading,
+            TokenKind::Emphasis,
+            TokenKind::Variable,
+        ]
+        .into_iter()
+        .find(|kind| kind.name() == name)
+    }
+}
+
+// A byte range of a line that gets a color of its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub range: Range<usize>,
+    pub kind: TokenKind,
+}
+
+// Where a line leaves off for the next one.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum State {
+    #[default]
+    Normal,
+    // Inside a block comment, nested `depth` deep.
+    Comment(usize),
+    // Inside the string that opens with the grammar's `strings[n]`.
+    String(usize),
+}
+
+// What a token covering `at` is, if any. `tokens` are sorted.
+pub fn kind_at(tokens: &[Token], at: usize) -> Option<TokenKind> {
+    let i = tokens.partition_point(|token| token.range.end <= at);
+    tokens
+        .get(i)
+        .filter(|token| token.range.start <= at)
+        .map(|token| token.kind)
+}
+
+struct Line {
+    start: State,
+    end: State,
+    tokens: Vec<Token>,
+}
+
+// Keeps the tokens of each line of a buffer around, so only the lines that
+// were edited get lexed again, along with the ones after them whose state
+// the edit changed, like when opening a block comment.
+#[derive(Default)]
+pub struct Highlighter {
+    // Index into `Grammars`, None for plain text.
+    grammar: Option<usize>,
+    // The buffer as it was last seen.
+    revision: usize,
+    line_count: usize,
+    // None for lines changed since. The ones before `stale` are known
+    // to follow from the lines above them.
+    lines: Vec<Option<Line>>,
+    stale: usize,
+}
+
+impl Highlighter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Catches up with `buffer` and lexes whatever is needed so the lines
+    // before `end` are up to date.
+    pub fn update(&mut self, buffer: &Buffer, grammars: &Grammars, end: usize) {
+        self.invalidate(buffer, grammars);
+        let Some(grammar) = self.grammar.map(|i| grammars.get(i)) else {
+            return;
+        };
+        let end = end.min(buffer.line_count());
+        while self.stale < end {
+            let y = self.stale;
+            let start = match y {
+                0 => State::Normal,
+                _ => self.lines[y - 1]
+                    .as_ref()
+                    .map(|line| line.end.clone())
+                    .unwrap_or_default(),
+            };
+            let cached = self.lines.get(y).and_then(Option::as_ref);
+            if cached.is_none_or(|line| line.start != start) {
+                let (tokens, end) = grammar.lex(&buffer.line(y), &start);
+                let line = Some(Line { start, end, tokens });
+                if y < self.lines.len() {
+                    self.lines[y] = line;
+                } else {
+                    self.lines.push(line);
+                }
+            }
+            self.stale += 1;
+        }
+    }
+
+    fn invalidate(&mut self, buffer: &Buffer, grammars: &Grammars) {
+        let grammar = buffer
+            .filepath()
+            .and_then(|path| grammars.detect(path, &buffer.line(0)));
+        if grammar != self.grammar {
+            self.grammar = grammar;
+            self.lines.clear();
+            self.stale = 0;
+        }
+        if let Some(changed) = buffer.changed_since(self.revision) {
+            // Lines `first..=last` took the place of `first..=old_last`,
+            // the ones after them only moved.
+            let first = buffer.line_of(changed.start);
+            let last = buffer.line_of(changed.end);
+            let old_last = (last + self.line_count).saturating_sub(buffer.line_count());
+            if first < self.lines.len() {
+                let moved = self.lines.split_off((old_last + 1).min(self.lines.len()));
+                self.lines.truncate(first);
+                if !moved.is_empty() {
+                    self.lines.resize_with(last + 1, || None);
+                    self.lines.extend(moved);
+                }
+            }
+            self.stale = self.stale.min(first);
+        }
+        self.revision = buffer.revision();
+        self.line_count = buffer.line_count();
+    }
+
+    // Only the lines `update` got to have any.
+    pub fn tokens(&self, y: usize) -> &[Token] {
+        match self.lines[..self.stale].get(y) {
+            Some(Some(line)) => &line.tokens,
+            _ => &[],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_highlighter() {
+        let mut buffer = Buffer::new();
+        buffer.insert_text("fn a() {}\nfn b() {}\nfn c() {}\nfn d() {}");
+        let dir = TestDir::new("highlighter");
+        buffer.save_as(dir.join("main.rs")).unwrap();
+        let grammars = Grammars::builtin();
+        let mut highlighter = Highlighter::new();
+        highlighter.update(&buffer, &grammars, 4);
+        assert_eq!(highlighter.tokens(3).len(), 1);
+
+        // Only the edited line needs lexing again, the others only move.
+        buffer.set_cursor_offset(buffer.line_start(1));
+        buffer.insert_text("/*\n");
+        highlighter.update(&buffer, &grammars, 0);
+        let missing: Vec<_> = highlighter.lines.iter().map(Option::is_none).collect();
+        assert_eq!(missing, [false, true, true, false, false]);
+
+        // Though the comment carries on to the lines after it.
+        highlighter.update(&buffer, &grammars, 5);
+        assert_eq!(kind_at(highlighter.tokens(4), 0), Some(TokenKind::Comment));
+        assert_eq!(kind_at(highlighter.tokens(0), 0), Some(TokenKind::Keyword));
+        assert_eq!(kind_at(highlighter.tokens(0), 2), None);
+
+        buffer.undo();
+        highlighter.update(&buffer, &grammars, 5);
+        assert_eq!(highlighter.lines.len(), 4);
+        assert_eq!(kind_at(highlighter.tokens(3), 0), Some(TokenKind::Keyword));
+    }
+}
@@ -0,0 +1,113 @@
+use crate::buffer::Cursor;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Edit {
+    Insert { at: usize, text: String },
+    Delete { at: usize, text: String },
+}
+
+impl Edit {
+    pub fn inverse(&self) -> Self {
+        match self {
+            Edit::Insert { at, text } => Edit::Delete {
+                at: *at,
+                text: text.clone(),
+            },
+            Edit::Delete { at, text } => Edit::Insert {
+                at: *at,
+                text: text.clone(),
+            },
+        }
+    }
+}
+
+// What a single undo/redo reverts/replays.
+#[derive(Debug, Clone)]
+pub struct Step {
+    // Identifies the state of the text after this step, see `History::state`.
+    pub id: usize,
+    pub edits: Vec<Edit>,
+    pub cursor_before: Cursor,
+    pub cursor_after: Cursor,
+}
+
+#[derive(Debug, Default)]
+pub struct History {
+    undo: Vec<Step>,
+    redo: Vec<Step>,
+    // Edits done by the operation in progress, see `commit`.
+    pending: Vec<Edit>,
+    // Whether the top of `undo` was typed and can take more typed text.
+    coalescing: bool,
+    last_id: usize,
+}
+
+impl History {
+    pub fn push(&mut self, edit: Edit) {
+        self.pending.push(edit);
+    }
+
+    // Groups the pending edits into one step.
+    // Typed text gets appended to the previous step when it continues it,
+    // so undo removes a whole run of typing instead of one char at a time.
+    pub fn commit(&mut self, cursor_before: Cursor, cursor_after: Cursor, typed: bool) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let edits = std::mem::take(&mut self.pending);
+        self.redo.clear();
+
+        if typed && self.coalescing {
+            if let Some(last) = self.undo.last_mut() {
+                if last.cursor_after == cursor_before && continues(&last.edits, &edits) {
+                    last.edits.extend(edits);
+                    last.cursor_after = cursor_after;
+                    return;
+                }
+            }
+        }
+
+        self.last_id += 1;
+        self.undo.push(Step {
+            id: self.last_id,
+            edits,
+            cursor_before,
+            cursor_after,
+        });
+        self.coalescing = typed;
+    }
+
+    // Every state of the text reachable through undo/redo has its own id,
+    // the unedited text being 0.
+    pub fn state(&self) -> usize {
+        self.undo.last().map_or(0, |step| step.id)
+    }
+
+    // Stops the next typed text from merging into the last step.
+    pub fn seal(&mut self) {
+        self.coalescing = false;
+    }
+
+    pub fn undo(&mut self) -> Option<Step> {
+        self.coalescing = false;
+        let step = self.undo.pop()?;
+        self.redo.push(step.clone());
+        Some(step)
+    }
+
+    pub fn redo(&mut self) -> Option<Step> {
+        self.coalescing = false;
+        let step = self.redo.pop()?;
+        self.undo.push(step.clone());
+        Some(step)
+    }
+}
+
+fn continues(prev: &[Edit], next: &[Edit]) -> bool {
+    match (prev.last(), next.first()) {
+        (Some(Edit::Insert { at, text }), Some(Edit::Insert { at: next_at, .. })) => {
+            at + text.len() == *next_at
+        }
+        _ => false,
+    }
+}
@@ -11,8 +11,8 @@ pub struct Image {
 }
 
 impl Image {
-    pub fn load(file_path: &str) -> Self {
-        let c_path = CString::new(file_path).unwrap();
+    pub fn load(file_path: &str) -> Result<Self, String> {
+        let c_path = CString::new(file_path).map_err(|e| e.to_string())?;
 
         let mut width = 0;
         let mut height = 0;
@@ -30,15 +30,15 @@ impl Image {
         };
 
         if pixels.is_null() {
-            panic!("image is null after load");
+            return Err(format!("{}: couldn't load image", file_path));
         }
 
-        Image {
+        Ok(Image {
             pixels: unsafe { std::slice::from_raw_parts(pixels, (width * height * 4) as usize) }
                 .to_vec(),
             width,
             height,
-        }
+        })
     }
 }
 
@@ -46,7 +46,7 @@ use std::mem;
 
 impl Drop for Image {
     fn drop(&mut self) {
-        let mut pixels = mem::replace(&mut self.pixels, vec![]);
+        let mut pixels = mem::take(&mut self.pixels);
         unsafe { stbi_image_free(pixels.as_mut_ptr() as *mut c_void) };
         mem::forget(pixels);
     }
@@ -0,0 +1,162 @@
+use std::fmt;
+use std::str::FromStr;
+
+// Keys as the editor sees them, whatever the window system calls them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KeyCode {
+    Char(char),
+    Escape,
+    Return,
+    Backspace,
+    Delete,
+    Tab,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    F(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key {
+    pub code: KeyCode,
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+impl Key {
+    pub const fn new(code: KeyCode) -> Self {
+        Self {
+            code,
+            ctrl: false,
+            shift: false,
+            alt: false,
+        }
+    }
+
+    pub const fn ctrl(code: KeyCode) -> Self {
+        Self {
+            ctrl: true,
+            ..Self::new(code)
+        }
+    }
+
+    // Plain text, as in what typing it would insert.
+    pub fn char(&self) -> Option<char> {
+        match self.code {
+            KeyCode::Char(ch) if !self.ctrl && !self.alt => Some(ch),
+            _ => None,
+        }
+    }
+}
+
+impl From<char> for Key {
+    fn from(ch: char) -> Self {
+        Self::new(KeyCode::Char(ch))
+    }
+}
+
+const NAMES: &[(KeyCode, &str)] = &[
+    (KeyCode::Char(' '), "Space"),
+    (KeyCode::Escape, "Esc"),
+    (KeyCode::Return, "Return"),
+    (KeyCode::Backspace, "Backspace"),
+    (KeyCode::Delete, "Delete"),
+    (KeyCode::Tab, "Tab"),
+    (KeyCode::Left, "Left"),
+    (KeyCode::Right, "Right"),
+    (KeyCode::Up, "Up"),
+    (KeyCode::Down, "Down"),
+    (KeyCode::Home, "Home"),
+    (KeyCode::End, "End"),
+    (KeyCode::PageUp, "PageUp"),
+    (KeyCode::PageDown, "PageDown"),
+];
+
+// Emacs style chords, like `C-x`, `C-S-z`, `M-Left` or `F2`.
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut key = Key::new(KeyCode::Escape);
+        let mut rest = s;
+        loop {
+            match rest.split_once('-') {
+                Some(("C", r)) if !r.is_empty() => key.ctrl = true,
+                Some(("S", r)) if !r.is_empty() => key.shift = true,
+                Some(("M", r)) if !r.is_empty() => key.alt = true,
+                _ => break,
+            }
+            rest = &rest[2..];
+        }
+        let mut chars = rest.chars();
+        key.code = match (chars.next(), chars.next()) {
+            (Some(ch), None) => KeyCode::Char(ch),
+            _ => match NAMES
+                .iter()
+                .find(|(_, name)| name.eq_ignore_ascii_case(rest))
+            {
+                Some(&(code, _)) => code,
+                None => match rest.strip_prefix('F').and_then(|n| n.parse().ok()) {
+                    Some(n @ 1..=24) => KeyCode::F(n),
+                    _ => return Err(format!("unknown key `{}`", s)),
+                },
+            },
+        };
+        // That's how they come in with a modifier, `C-Z` being `C-S-z`.
+        if let KeyCode::Char(ch) = key.code {
+            if ch.is_ascii_uppercase() && (key.ctrl || key.alt) {
+                key.code = KeyCode::Char(ch.to_ascii_lowercase());
+                key.shift = true;
+            }
+        }
+        Ok(key)
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (on, modifier) in [(self.ctrl, "C-"), (self.alt, "M-"), (self.shift, "S-")] {
+            if on {
+                f.write_str(modifier)?;
+            }
+        }
+        match self.code {
+            KeyCode::F(n) => write!(f, "F{}", n),
+            code => match NAMES.iter().find(|(c, _)| *c == code) {
+                Some((_, name)) => f.write_str(name),
+                None => match code {
+                    KeyCode::Char(ch) => write!(f, "{}", ch),
+                    _ => unreachable!(),
+                },
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_chords() {
+        let key = |s: &str| s.parse::<Key>().unwrap();
+        assert_eq!(key("a"), Key::from('a'));
+        assert_eq!(key("-"), Key::from('-'));
+        assert_eq!(key("C--"), Key::ctrl(KeyCode::Char('-')));
+        assert_eq!(key("F2"), Key::new(KeyCode::F(2)));
+        assert_eq!(key("return"), Key::new(KeyCode::Return));
+        assert_eq!(key("C-Z"), key("C-S-z"));
+        for chord in ["C-M-S-Left", "S-F12", "C-x", "Space", "C-Esc"] {
+            assert_eq!(key(chord).to_string(), chord);
+        }
+        assert!("C-".parse::<Key>().is_err());
+        assert!("F25".parse::<Key>().is_err());
+        assert!("Hyper-x".parse::<Key>().is_err());
+    }
+}
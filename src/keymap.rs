@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+
+use crate::command::Command;
+use crate::key::Key;
+
+// Same format as the user's keymap file, which goes on top of it.
+// Each line binds a sequence of chords to a command, or to `none` to unbind
+// it. A sequence that starts a longer one only ever waits for the rest.
+const DEFAULT: &str = "\
+Left = cursor.left
+Right = cursor.right
+Up = cursor.up
+Down = cursor.down
+S-Left = select.left
+S-Right = select.right
+S-Up = select.up
+S-Down = select.down
+Return = edit.newline
+Backspace = edit.backspace
+Delete = edit.delete
+C-z = edit.undo
+C-S-z = edit.redo
+C-c = edit.copy
+C-x = edit.cut
+C-v = edit.paste
+C-f = search.start
+C-s = buffer.save
+F2 = buffer.save
+S-F2 = buffer.save_as
+C-o = buffer.open
+C-w = buffer.close
+C-b = buffer.list
+C-PageDown = buffer.next
+C-PageUp = buffer.prev
+C-Tab = buffer.next
+C-S-Tab = buffer.prev
+F6 = buffer.toggle_line_ending
+F3 = pane.split_horizontal
+S-F3 = pane.split_vertical
+C-F3 = pane.close
+F4 = pane.next
+S-F4 = pane.prev
+C-g = cursor.goto_line
+M-x = editor.palette
+C-S-p = editor.palette
+C-q = editor.quit
+";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Lookup {
+    Command(Command),
+    // Part of a longer sequence.
+    Pending,
+    Unbound,
+}
+
+pub struct Keymap {
+    bindings: HashMap<Vec<Key>, Command>,
+    pending: Vec<Key>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let mut keymap = Self::empty();
+        keymap.load(DEFAULT).expect("the default keymap is valid");
+        keymap
+    }
+}
+
+impl Keymap {
+    pub fn empty() -> Self {
+        Self {
+            bindings: HashMap::new(),
+            pending: vec![],
+        }
+    }
+
+    pub fn bind(&mut self, keys: Vec<Key>, command: Option<Command>) {
+        match command {
+            Some(command) => self.bindings.insert(keys, command),
+            None => self.bindings.remove(&keys),
+        };
+    }
+
+    // Lines like `C-x C-s = buffer.save`, blank ones and `#` comments
+    // are skipped. Stops at the first line that doesn't make sense.
+    pub fn load(&mut self, text: &str) -> Result<(), String> {
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (keys, command) =
+                parse_line(line).map_err(|err| format!("line {}: {}", i + 1, err))?;
+            self.bind(keys, command);
+        }
+        Ok(())
+    }
+
+    pub fn feed(&mut self, key: Key) -> Lookup {
+        self.pending.push(key);
+        let pending = &self.pending;
+        let is_prefix = self
+            .bindings
+            .keys()
+            .any(|keys| keys.len() > pending.len() && keys.starts_with(pending));
+        if is_prefix {
+            return Lookup::Pending;
+        }
+        let keys = std::mem::take(&mut self.pending);
+        match self.bindings.get(&keys) {
+            Some(&command) => Lookup::Command(command),
+            None => Lookup::Unbound,
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    // The chords typed so far, as in "C-x-".
+    pub fn pending(&self) -> String {
+        self.pending
+            .iter()
+            .map(|key| format!("{}-", key))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    pub fn keys_for(&self, command: Command) -> Vec<Vec<Key>> {
+        let mut keys: Vec<_> = self
+            .bindings
+            .iter()
+            .filter(|(_, c)| **c == command)
+            .map(|(keys, _)| keys.clone())
+            .collect();
+        keys.sort_by_key(|keys| (keys.len(), format!("{:?}", keys)));
+        keys
+    }
+}
+
+fn parse_line(line: &str) -> Result<(Vec<Key>, Option<Command>), String> {
+    let (keys, name) = line
+        .rsplit_once('=')
+        .ok_or_else(|| "expected `keys = command`".to_string())?;
+    let keys = keys
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<Vec<Key>, _>>()?;
+    if keys.is_empty() {
+        return Err("no keys to bind".into());
+    }
+    let command = match name.trim() {
+        "none" => None,
+        name => {
+            Some(Command::from_name(name).ok_or_else(|| format!("unknown command `{}`", name))?)
+        }
+    };
+    Ok((keys, command))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::key::KeyCode;
+
+    #[test]
+    fn test_keymap() {
+        let mut keymap = Keymap::default();
+        let key = |s: &str| s.parse::<Key>().unwrap();
+        assert_eq!(keymap.feed(key("C-s")), Lookup::Command(Command::Save));
+        assert_eq!(keymap.feed(key("C-S-z")), Lookup::Command(Command::Redo));
+        assert_eq!(keymap.feed(key("C-y")), Lookup::Unbound);
+
+        keymap
+            .load("# emacs\nC-x C-s = buffer.save\nC-x b = buffer.list\nC-s = none\n")
+            .unwrap();
+        assert_eq!(keymap.feed(key("C-s")), Lookup::Unbound);
+        // Cutting is shadowed by the longer sequences.
+        assert_eq!(keymap.feed(key("C-x")), Lookup::Pending);
+        assert_eq!(keymap.pending(), "C-x-");
+        assert_eq!(keymap.feed(key("C-s")), Lookup::Command(Command::Save));
+        assert!(!keymap.is_pending());
+        keymap.feed(key("C-x"));
+        assert_eq!(keymap.feed(Key::new(KeyCode::Escape)), Lookup::Unbound);
+        keymap.feed(key("C-x"));
+        assert_eq!(
+            keymap.feed(Key::from('b')),
+            Lookup::Command(Command::ListBuffers)
+        );
+        assert_eq!(
+            keymap.keys_for(Command::Save),
+            [vec![key("F2")], vec![key("C-x"), key("C-s")]]
+        );
+
+        assert_eq!(
+            keymap.load("F5 = nope").unwrap_err(),
+            "line 1: unknown command `nope`"
+        );
+        assert_eq!(
+            keymap.load("\nC-x C- = edit.cut").unwrap_err(),
+            "line 2: unknown key `C-`"
+        );
+        assert!(keymap.load("= edit.cut").is_err());
+        assert!(keymap.load("C-x edit.cut").is_err());
+    }
+}
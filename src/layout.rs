@@ -0,0 +1,174 @@
+// Gap between panes, in pixels.
+const SEPARATOR: i32 = 2;
+
+// In window pixels, from the top left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Split {
+    // One pane above the other.
+    Horizontal,
+    // Side by side.
+    Vertical,
+}
+
+// How the window is divided into panes, each showing a view.
+#[derive(Debug)]
+pub enum Layout {
+    Pane(usize),
+    Split {
+        split: Split,
+        first: Box<Layout>,
+        second: Box<Layout>,
+    },
+}
+
+impl Layout {
+    // Puts `new` next to `pane`, sharing its space.
+    pub fn split(&mut self, pane: usize, split: Split, new: usize) -> bool {
+        match self {
+            Layout::Pane(p) if *p == pane => {
+                *self = Layout::Split {
+                    split,
+                    first: Box::new(Layout::Pane(pane)),
+                    second: Box::new(Layout::Pane(new)),
+                };
+                true
+            }
+            Layout::Pane(_) => false,
+            Layout::Split { first, second, .. } => {
+                first.split(pane, split, new) || second.split(pane, split, new)
+            }
+        }
+    }
+
+    // Gives the space of `pane` to whatever it was split from, and since views
+    // are numbered by their position, the ones after it take its number.
+    pub fn remove(&mut self, pane: usize) {
+        self.take(pane);
+        self.renumber(pane);
+    }
+
+    fn take(&mut self, pane: usize) -> bool {
+        let Layout::Split { first, second, .. } = self else {
+            return false;
+        };
+        let kept = match (&**first, &**second) {
+            (Layout::Pane(p), _) if *p == pane => second,
+            (_, Layout::Pane(p)) if *p == pane => first,
+            _ => return first.take(pane) || second.take(pane),
+        };
+        let kept = std::mem::replace(&mut **kept, Layout::Pane(pane));
+        *self = kept;
+        true
+    }
+
+    fn renumber(&mut self, removed: usize) {
+        match self {
+            Layout::Pane(p) if *p > removed => *p -= 1,
+            Layout::Pane(_) => {}
+            Layout::Split { first, second, .. } => {
+                first.renumber(removed);
+                second.renumber(removed);
+            }
+        }
+    }
+
+    // Left to right, top to bottom.
+    pub fn panes(&self) -> Vec<usize> {
+        self.rects(Rect {
+            x: 0,
+            y: 0,
+            w: 0,
+            h: 0,
+        })
+        .into_iter()
+        .map(|(pane, _)| pane)
+        .collect()
+    }
+
+    pub fn rects(&self, rect: Rect) -> Vec<(usize, Rect)> {
+        let mut rects = vec![];
+        self.collect_rects(rect, &mut rects);
+        rects
+    }
+
+    fn collect_rects(&self, rect: Rect, rects: &mut Vec<(usize, Rect)>) {
+        let (split, first, second) = match self {
+            Layout::Pane(pane) => {
+                rects.push((*pane, rect));
+                return;
+            }
+            Layout::Split {
+                split,
+                first,
+                second,
+            } => (split, first, second),
+        };
+        let (a, b) = match split {
+            Split::Horizontal => {
+                let h = (rect.h - SEPARATOR).max(0);
+                let top = Rect { h: h / 2, ..rect };
+                let bottom = Rect {
+                    y: rect.y + h / 2 + SEPARATOR,
+                    h: h - h / 2,
+                    ..rect
+                };
+                (top, bottom)
+            }
+            Split::Vertical => {
+                let w = (rect.w - SEPARATOR).max(0);
+                let left = Rect { w: w / 2, ..rect };
+                let right = Rect {
+                    x: rect.x + w / 2 + SEPARATOR,
+                    w: w - w / 2,
+                    ..rect
+                };
+                (left, right)
+            }
+        };
+        first.collect_rects(a, rects);
+        second.collect_rects(b, rects);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i32, y: i32, w: i32, h: i32) -> Rect {
+        Rect { x, y, w, h }
+    }
+
+    #[test]
+    fn test_layout() {
+        let window = rect(0, 0, 102, 52);
+        let mut layout = Layout::Pane(0);
+        assert!(layout.split(0, Split::Vertical, 1));
+        assert!(layout.split(1, Split::Horizontal, 2));
+        assert!(!layout.split(3, Split::Horizontal, 4));
+        assert_eq!(
+            layout.rects(window),
+            [
+                (0, rect(0, 0, 50, 52)),
+                (1, rect(52, 0, 50, 25)),
+                (2, rect(52, 27, 50, 25)),
+            ]
+        );
+
+        layout.remove(1);
+        assert_eq!(layout.panes(), [0, 1]);
+        assert_eq!(
+            layout.rects(window),
+            [(0, rect(0, 0, 50, 52)), (1, rect(52, 0, 50, 52))]
+        );
+        layout.remove(0);
+        assert_eq!(layout.rects(window), [(0, window)]);
+    }
+}
@@ -1,13 +1,42 @@
 pub mod buffer;
+pub mod command;
+pub mod config;
+pub mod editor;
+pub mod font;
 pub mod gl_extra;
+pub mod grammar;
+pub mod gutter;
+pub mod highlight;
+pub mod history;
 pub mod image;
+pub mod key;
+pub mod keymap;
+pub mod layout;
+pub mod minibuffer;
+pub mod modal;
+pub mod regex;
+pub mod search;
 pub mod shaders;
 pub mod small_array;
+pub mod status;
+pub mod swap;
+#[cfg(test)]
+mod test_dir;
+pub mod theme;
 pub mod tile_glyph;
+pub mod toml;
 pub mod vector;
+pub mod watch;
 
 pub use vector::{Vector2, Vector4};
 
 pub type Color = vector::Vector4<f32>;
 pub const BLACK: Color = crate::v4s!(0.0);
 pub const WHITE: Color = crate::v4s!(1.0);
+pub const GRAY: Color = crate::v4!(0.5, 0.5, 0.5, 1.0);
+pub const YELLOW: Color = crate::v4!(1.0, 1.0, 0.0, 1.0);
+pub const RED: Color = crate::v4!(1.0, 0.3, 0.3, 1.0);
+pub const GREEN: Color = crate::v4!(0.5, 0.9, 0.4, 1.0);
+pub const CYAN: Color = crate::v4!(0.4, 0.8, 0.9, 1.0);
+pub const MAGENTA: Color = crate::v4!(0.9, 0.5, 0.9, 1.0);
+pub const ORANGE: Color = crate::v4!(1.0, 0.6, 0.2, 1.0);
@@ -1,32 +1,155 @@
+use red::layout::{Rect, Split};
+use red::search::Search;
 use red::tile_glyph::TileGlyphBuffer;
-use red::BLACK;
-use red::WHITE;
+use std::path::PathBuf;
+
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
+use sdl2::keyboard::Mod;
 
-use red::buffer::Buffer;
+use red::buffer::{display_width, DiskChange, LineEnding};
+use red::command::Command;
+use red::config::{self, config_dir, Config};
+use red::editor::Editor;
+use red::font::Font;
+use red::grammar::Grammars;
+use red::highlight;
+use red::key::{Key, KeyCode};
+use red::keymap::{Keymap, Lookup};
+use red::minibuffer::Minibuffer;
+use red::modal::Vi;
+use red::status::{self, Status};
+use red::theme::{Theme, Themes};
 use red::vector::Vector2;
 use red::{v2, v2s};
 
-// const SCREEN_WIDTH: u32 = 800;
-// const SCREEN_HEIGHT: u32 = 600;
-const SCREEN_WIDTH: u32 = 1280;
-const SCREEN_HEIGHT: u32 = 720;
-const FPS: u32 = 60;
-const DELTA_TIME: f32 = 1.0 / FPS as f32;
+// How often unsaved changes get journaled to the swap file.
+const SWAP_INTERVAL_MS: u32 = 2000;
+
+const CTRL: Mod = Mod::LCTRLMOD.union(Mod::RCTRLMOD);
+const SHIFT: Mod = Mod::LSHIFTMOD.union(Mod::RSHIFTMOD);
+const ALT: Mod = Mod::LALTMOD.union(Mod::RALTMOD);
 
-// const FONT_SCALE: f32 = 5.0;
-const FONT_SCALE: f32 = 3.0;
-const FONT_WIDTH: usize = 128;
-const FONT_HEIGHT: usize = 64;
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Edit,
+    Search,
+    Replace,
+    SaveAs,
+    // Closing a buffer with unsaved changes, or quitting with any.
+    ConfirmClose,
+    Recover,
+    // The file changed on disk while there were unsaved changes.
+    Changed,
+    // Saving would clobber changes someone else made to the file.
+    Overwrite,
+    Open,
+    Buffers,
+    GotoLine,
+    // Running a command by name.
+    Palette,
+}
 
-const FONT_COLS: usize = 18;
-const FONT_ROWS: usize = 7;
+impl Mode {
+    // Whether what's typed goes into the minibuffer.
+    fn prompts(self) -> bool {
+        matches!(
+            self,
+            Mode::Search
+                | Mode::Replace
+                | Mode::SaveAs
+                | Mode::Open
+                | Mode::Buffers
+                | Mode::GotoLine
+                | Mode::Palette
+        )
+    }
+
+    // Whether it's asking something that a single key answers.
+    fn asks(self) -> bool {
+        matches!(
+            self,
+            Mode::ConfirmClose | Mode::Recover | Mode::Changed | Mode::Overwrite
+        )
+    }
+}
 
-const FONT_CHAR_WIDTH: usize = FONT_WIDTH / FONT_COLS;
-const FONT_CHAR_HEIGHT: usize = FONT_HEIGHT / FONT_ROWS;
+// Text comes in through `Event::TextInput`, so keys that type something
+// are only keys when they're combined with ctrl or alt.
+fn key_of(keycode: Keycode, keymod: Mod) -> Option<Key> {
+    let code = match keycode {
+        Keycode::Escape => KeyCode::Escape,
+        Keycode::Return | Keycode::KpEnter => KeyCode::Return,
+        Keycode::Backspace => KeyCode::Backspace,
+        Keycode::Delete => KeyCode::Delete,
+        Keycode::Tab => KeyCode::Tab,
+        Keycode::Left => KeyCode::Left,
+        Keycode::Right => KeyCode::Right,
+        Keycode::Up => KeyCode::Up,
+        Keycode::Down => KeyCode::Down,
+        Keycode::Home => KeyCode::Home,
+        Keycode::End => KeyCode::End,
+        Keycode::PageUp => KeyCode::PageUp,
+        Keycode::PageDown => KeyCode::PageDown,
+        Keycode::F1 => KeyCode::F(1),
+        Keycode::F2 => KeyCode::F(2),
+        Keycode::F3 => KeyCode::F(3),
+        Keycode::F4 => KeyCode::F(4),
+        Keycode::F5 => KeyCode::F(5),
+        Keycode::F6 => KeyCode::F(6),
+        Keycode::F7 => KeyCode::F(7),
+        Keycode::F8 => KeyCode::F(8),
+        Keycode::F9 => KeyCode::F(9),
+        Keycode::F10 => KeyCode::F(10),
+        Keycode::F11 => KeyCode::F(11),
+        Keycode::F12 => KeyCode::F(12),
+        _ if keymod.intersects(CTRL | ALT) => {
+            let ch = char::from_u32(keycode.into_i32() as u32).filter(char::is_ascii_graphic)?;
+            KeyCode::Char(ch)
+        }
+        _ => return None,
+    };
+    Some(Key {
+        code,
+        ctrl: keymod.intersects(CTRL),
+        shift: keymod.intersects(SHIFT),
+        alt: keymod.intersects(ALT),
+    })
+}
+
+// Closes the current buffer and, when quitting, moves on to the next one
+// with unsaved changes. Tells whether there's nothing left to quit.
+fn close_buffer(editor: &mut Editor, quitting: bool, mode: &mut Mode, status: &mut Status) -> bool {
+    if let Err(err) = editor.close_current() {
+        status.error(err);
+    }
+    *mode = Mode::Edit;
+    if !quitting {
+        return false;
+    }
+    match editor.first_modified() {
+        Some(i) => {
+            editor.switch_to(i);
+            *mode = Mode::ConfirmClose;
+            false
+        }
+        None => true,
+    }
+}
 
 fn main() -> Result<(), String> {
+    let mut status = Status::default();
+    // A broken config shouldn't keep anyone from editing it.
+    let config = match config_dir().map(|dir| Config::load(&dir.join("config.toml"))) {
+        Some(Ok(config)) => config,
+        Some(Err(err)) => {
+            status.error(err);
+            Config::default()
+        }
+        None => Config::default(),
+    };
+    let delta_time = 1.0 / config.fps as f32;
+
     let sdl_context = sdl2::init()?;
     let video_subsystem = sdl_context.video()?;
 
@@ -36,118 +159,816 @@ fn main() -> Result<(), String> {
     // let (gl_ver_maj, gl_ver_min) = gl_attr.context_version();
     // println!("opengl version: {}.{}", gl_ver_maj, gl_ver_min);
 
-    let window = video_subsystem
-        .window("red", SCREEN_WIDTH, SCREEN_HEIGHT)
+    let mut window = video_subsystem
+        .window("red", config.width, config.height)
         .opengl()
         .resizable()
         .build()
         .map_err(|e| e.to_string())?;
 
     let _gl_context = window.gl_create_context()?;
-    let _gl =
-        gl::load_with(|s| video_subsystem.gl_get_proc_address(s) as *const std::os::raw::c_void);
+    gl::load_with(|s| video_subsystem.gl_get_proc_address(s) as *const std::os::raw::c_void);
 
     unsafe {
         gl::Enable(gl::BLEND);
         gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
     }
 
-    let mut glyph_buf = TileGlyphBuffer::new();
+    let font = match Font::load(&config.font) {
+        Ok(font) => font,
+        Err(err) => {
+            status.error(err);
+            Font::load(&config::Font::default())?
+        }
+    };
+    let mut glyph_buf = TileGlyphBuffer::new(font);
 
     glyph_buf.gl_init();
-    glyph_buf.load_texture_atlas("charmap-oldschool_white.png");
     glyph_buf.compile_shaders("shaders/tile_glyph.vert", "shaders/tile_glyph.frag")?;
 
-    let mut buffer = if let Some(filepath) = std::env::args().skip(1).next() {
-        Buffer::from_filepath(filepath).map_err(|e| e.to_string())?
-    } else {
-        Buffer::new()
+    let mut editor = Editor::new();
+    let mut vi = None;
+    for arg in std::env::args().skip(1) {
+        if arg == "--vi" {
+            vi = Some(Vi::new());
+            continue;
+        }
+        // One that can't be opened shouldn't cost the others.
+        if let Err(err) = editor.open(arg.clone()) {
+            status.error(format!("{}: {}", arg, err));
+        }
+    }
+    editor.switch_to(0);
+
+    let mut keymap = Keymap::default();
+    if let Some(path) = config_dir().map(|dir| dir.join("keymap")) {
+        match std::fs::read_to_string(&path) {
+            Ok(text) => {
+                if let Err(err) = keymap.load(&text) {
+                    status.error(format!("{}: {}", path.display(), err));
+                }
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => status.error(format!("{}: {}", path.display(), err)),
+        }
+    }
+
+    let mut themes = Themes::builtin();
+    if let Some(dir) = config_dir().map(|dir| dir.join("themes")) {
+        if let Err(err) = themes.load_dir(&dir) {
+            status.error(err);
+        }
+    }
+    let mut theme = match themes.get(&config.theme) {
+        Some(theme) => theme.with(&config.colors),
+        None => {
+            status.error(format!("theme `{}` not found", config.theme));
+            Theme::default().with(&config.colors)
+        }
     };
 
+    let mut grammars = Grammars::builtin();
+    if let Some(dir) = config_dir().map(|dir| dir.join("grammars")) {
+        if let Err(err) = grammars.load_dir(&dir) {
+            status.error(err);
+        }
+    }
+
+    let clipboard = video_subsystem.clipboard();
     let timer = sdl_context.timer()?;
 
-    let mut camera_pos = v2s!(0.0);
     let mut camera_vel;
 
+    let mut mode = Mode::Edit;
+    let mut minibuffer = Minibuffer::new();
+    let mut line_numbers = config.line_numbers;
+    let mut quitting = false;
+    let mut close_after_save = false;
+    let mut search = Search::default();
+    let mut last_swap = timer.ticks();
+    // Some platforms follow chords like M-x with the text they'd type.
+    let mut swallow_text = false;
+
     let mut event_pump = sdl_context.event_pump()?;
     let mut quit = false;
     while !quit {
         let start = timer.ticks();
-        for event in event_pump.poll_iter() {
+        for mut event in event_pump.poll_iter() {
+            match event {
+                Event::KeyDown { .. } => {
+                    status.clear();
+                    // The text of a key that answers a question would go to
+                    // whatever mode the answer leads to.
+                    swallow_text = mode.asks();
+                }
+                Event::TextInput { .. } if swallow_text => {
+                    swallow_text = false;
+                    continue;
+                }
+                _ => {}
+            }
+            let buffer = editor.buffer_mut();
+            if let Some(vi) = vi.as_mut().filter(|_| mode == Mode::Edit) {
+                match &mut event {
+                    Event::KeyDown {
+                        keycode: Some(keycode),
+                        keymod,
+                        ..
+                    } if key_of(*keycode, *keymod).is_some_and(|key| vi.handle(key, buffer)) => {
+                        continue
+                    }
+                    // Only what vi left alone goes on, so nothing happens twice.
+                    Event::TextInput { text, .. } => {
+                        let rest: String = text
+                            .chars()
+                            .filter(|&ch| !vi.handle(Key::from(ch), buffer))
+                            .collect();
+                        if rest.is_empty() {
+                            continue;
+                        }
+                        *text = rest;
+                    }
+                    _ => {}
+                }
+            }
+            let mut command = None;
+            let mut edited = false;
             match event {
-                Event::Quit { .. } => quit = true,
-                Event::KeyDown { keycode, .. } => match keycode {
-                    Some(key) => match key {
-                        Keycode::F2 => match buffer.save() {
-                            Ok(_) => println!("saved file!"),
-                            Err(err) => eprintln!("{}", err),
-                        },
-                        Keycode::Backspace => buffer.backspace(),
-                        Keycode::Delete => buffer.delete(),
-                        Keycode::Left => buffer.move_left(),
-                        Keycode::Right => buffer.move_right(),
-                        Keycode::Up => buffer.move_up(),
-                        Keycode::Down => buffer.move_down(),
-                        Keycode::Return => buffer.newline(),
-                        _ => {}
+                Event::Quit { .. } => command = Some(Command::Quit),
+                Event::KeyDown {
+                    keycode: Some(key), ..
+                } if mode == Mode::ConfirmClose => match key {
+                    Keycode::S if buffer.filepath().is_none() => {
+                        mode = Mode::SaveAs;
+                        minibuffer.start("");
+                        close_after_save = true;
+                    }
+                    Keycode::S if buffer.changed_on_disk() => {
+                        mode = Mode::Overwrite;
+                        close_after_save = true;
+                    }
+                    Keycode::S => match buffer.save() {
+                        Ok(_) => quit = close_buffer(&mut editor, quitting, &mut mode, &mut status),
+                        Err(err) => {
+                            status.error(err);
+                            mode = Mode::Edit;
+                            quitting = false;
+                        }
                     },
+                    Keycode::D => {
+                        quit = close_buffer(&mut editor, quitting, &mut mode, &mut status)
+                    }
+                    Keycode::C | Keycode::Escape => {
+                        mode = Mode::Edit;
+                        quitting = false;
+                    }
+                    _ => {}
+                },
+                Event::TextInput { .. } if mode.asks() => {}
+                Event::KeyDown {
+                    keycode: Some(key), ..
+                } if mode == Mode::Recover => match key {
+                    Keycode::R => {
+                        buffer.recover();
+                        mode = Mode::Edit;
+                    }
+                    Keycode::D => {
+                        if let Err(err) = buffer.discard_recovery() {
+                            status.error(err);
+                        }
+                        mode = Mode::Edit;
+                    }
                     _ => {}
                 },
+                Event::KeyDown {
+                    keycode: Some(key), ..
+                } if mode == Mode::Changed => match key {
+                    Keycode::R => {
+                        if let Err(err) = buffer.reload() {
+                            status.error(err);
+                        }
+                        mode = Mode::Edit;
+                    }
+                    Keycode::K | Keycode::Escape => mode = Mode::Edit,
+                    _ => {}
+                },
+                Event::KeyDown {
+                    keycode: Some(key), ..
+                } if mode == Mode::Overwrite => match key {
+                    Keycode::O => {
+                        mode = Mode::Edit;
+                        match buffer.save() {
+                            Ok(_) if close_after_save => {
+                                quit = close_buffer(&mut editor, quitting, &mut mode, &mut status)
+                            }
+                            Ok(_) => status.info("saved file"),
+                            Err(err) => {
+                                status.error(err);
+                                quitting = false;
+                            }
+                        }
+                        close_after_save = false;
+                    }
+                    Keycode::R => {
+                        if let Err(err) = buffer.reload() {
+                            status.error(err);
+                        }
+                        mode = Mode::Edit;
+                        quitting = false;
+                        close_after_save = false;
+                    }
+                    Keycode::C | Keycode::Escape => {
+                        mode = Mode::Edit;
+                        quitting = false;
+                        close_after_save = false;
+                    }
+                    _ => {}
+                },
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    keymod,
+                    ..
+                } if mode.prompts() => {
+                    let Some(key) = key_of(keycode, keymod) else {
+                        continue;
+                    };
+                    let searching = matches!(mode, Mode::Search | Mode::Replace);
+                    let text = minibuffer.text();
+                    match key.code {
+                        KeyCode::Escape => {
+                            mode = Mode::Edit;
+                            quitting = false;
+                            close_after_save = false;
+                            buffer.clear_selection();
+                        }
+                        KeyCode::Return if mode == Mode::Replace && key.ctrl => {
+                            search.replace_all(buffer);
+                        }
+                        KeyCode::Return if mode == Mode::Replace => search.replace(buffer),
+                        KeyCode::Return if mode == Mode::Search && key.shift => search.prev(buffer),
+                        KeyCode::Return if mode == Mode::Search => search.next(buffer),
+                        KeyCode::Char('f') if searching && key.ctrl => search.next(buffer),
+                        KeyCode::Char('r') if searching && key.ctrl => {
+                            mode = Mode::Replace;
+                            minibuffer.start(&search.replacement);
+                        }
+                        KeyCode::Char('i') if searching && key.ctrl => {
+                            search.case_insensitive = !search.case_insensitive;
+                            search.update(buffer);
+                        }
+                        KeyCode::Char('e') if searching && key.ctrl => {
+                            search.regex = !search.regex;
+                            search.update(buffer);
+                        }
+                        KeyCode::Return if mode == Mode::SaveAs && !text.is_empty() => {
+                            mode = Mode::Edit;
+                            match buffer.save_as(PathBuf::from(&text)) {
+                                Ok(_) if close_after_save => {
+                                    quit =
+                                        close_buffer(&mut editor, quitting, &mut mode, &mut status)
+                                }
+                                Ok(_) => status.info("saved file"),
+                                Err(err) => {
+                                    status.error(err);
+                                    quitting = false;
+                                }
+                            }
+                            close_after_save = false;
+                        }
+                        KeyCode::Return if mode == Mode::Open && !text.is_empty() => {
+                            if let Err(err) = editor.open(text) {
+                                status.error(err);
+                            }
+                            mode = Mode::Edit;
+                        }
+                        KeyCode::Return if mode == Mode::Buffers => {
+                            let count = editor.documents().len();
+                            if let Some(n) = text.trim().parse::<usize>().ok().filter(|&n| n > 0) {
+                                editor.switch_to(n.min(count) - 1);
+                            }
+                            mode = Mode::Edit;
+                        }
+                        KeyCode::Return if mode == Mode::GotoLine => {
+                            if let Some(n) = text.trim().parse::<usize>().ok().filter(|&n| n > 0) {
+                                let y = n.min(buffer.line_count()) - 1;
+                                buffer.clear_selection();
+                                buffer.set_cursor_offset(buffer.line_start(y));
+                                mode = Mode::Edit;
+                            }
+                        }
+                        KeyCode::Return if mode == Mode::Palette => {
+                            command = minibuffer.selection();
+                            mode = Mode::Edit;
+                        }
+                        KeyCode::Down if mode == Mode::Palette => minibuffer.select_next(),
+                        KeyCode::Up if mode == Mode::Palette => minibuffer.select_prev(),
+                        KeyCode::Tab if mode == Mode::Palette => {
+                            if let Some(selection) = minibuffer.selection() {
+                                minibuffer.start(selection.name());
+                            }
+                        }
+                        _ => edited = minibuffer.handle(key) == Some(true),
+                    }
+                }
+                Event::TextInput { text, .. } if mode.prompts() => {
+                    minibuffer.insert(&text);
+                    edited = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    keymod,
+                    ..
+                } => match key_of(keycode, keymod).map(|key| keymap.feed(key)) {
+                    Some(Lookup::Command(c)) => {
+                        command = Some(c);
+                        swallow_text = true;
+                    }
+                    Some(Lookup::Pending) => swallow_text = true,
+                    _ => {}
+                },
+                // Sequences can go on with plain keys, like `C-x b`.
+                Event::TextInput { text, .. } if keymap.is_pending() => {
+                    for ch in text.chars() {
+                        if let Lookup::Command(c) = keymap.feed(Key::from(ch)) {
+                            command = Some(c);
+                        }
+                    }
+                }
                 Event::TextInput { text, .. } => buffer.insert_text(&text),
                 _ => {}
             }
+
+            if edited {
+                match mode {
+                    Mode::Search => {
+                        search.query = minibuffer.text();
+                        search.update(editor.buffer_mut());
+                    }
+                    Mode::Replace => search.replacement = minibuffer.text(),
+                    Mode::Palette => minibuffer.complete(),
+                    _ => {}
+                }
+            }
+
+            let Some(command) = command else {
+                continue;
+            };
+            let buffer = editor.buffer_mut();
+            match command {
+                Command::CursorLeft
+                | Command::CursorRight
+                | Command::CursorUp
+                | Command::CursorDown => {
+                    buffer.clear_selection();
+                    match command {
+                        Command::CursorLeft => buffer.move_left(),
+                        Command::CursorRight => buffer.move_right(),
+                        Command::CursorUp => buffer.move_up(),
+                        _ => buffer.move_down(),
+                    }
+                }
+                Command::SelectLeft
+                | Command::SelectRight
+                | Command::SelectUp
+                | Command::SelectDown => {
+                    buffer.start_selection();
+                    match command {
+                        Command::SelectLeft => buffer.move_left(),
+                        Command::SelectRight => buffer.move_right(),
+                        Command::SelectUp => buffer.move_up(),
+                        _ => buffer.move_down(),
+                    }
+                }
+                Command::Newline => buffer.newline(),
+                Command::Backspace => buffer.backspace(),
+                Command::Delete => buffer.delete(),
+                Command::Undo => buffer.undo(),
+                Command::Redo => buffer.redo(),
+                Command::Copy => {
+                    if let Err(err) = clipboard.set_clipboard_text(&buffer.copy()) {
+                        status.error(err);
+                    }
+                }
+                Command::Cut => {
+                    if let Err(err) = clipboard.set_clipboard_text(&buffer.cut()) {
+                        status.error(err);
+                    }
+                }
+                Command::Paste => match clipboard.clipboard_text() {
+                    Ok(text) => buffer.paste(&text),
+                    Err(err) => status.error(err),
+                },
+                Command::Search => {
+                    mode = Mode::Search;
+                    search.start(buffer);
+                    minibuffer.start("");
+                }
+                Command::Save if buffer.filepath().is_none() => {
+                    mode = Mode::SaveAs;
+                    minibuffer.start("");
+                }
+                Command::Save if buffer.changed_on_disk() => mode = Mode::Overwrite,
+                Command::Save => match buffer.save() {
+                    Ok(_) => status.info("saved file"),
+                    Err(err) => status.error(err),
+                },
+                Command::SaveAs => {
+                    mode = Mode::SaveAs;
+                    let path = buffer
+                        .filepath()
+                        .map(|path| path.to_string_lossy().into_owned())
+                        .unwrap_or_default();
+                    minibuffer.start(&path);
+                }
+                Command::Open => {
+                    mode = Mode::Open;
+                    minibuffer.start("");
+                }
+                Command::Close if buffer.is_modified() => mode = Mode::ConfirmClose,
+                Command::Close => {
+                    close_buffer(&mut editor, false, &mut mode, &mut status);
+                }
+                Command::ListBuffers => {
+                    mode = Mode::Buffers;
+                    minibuffer.start("");
+                }
+                Command::NextBuffer => editor.next(),
+                Command::PrevBuffer => editor.prev(),
+                Command::ToggleLineEnding => {
+                    let line_ending = match buffer.line_ending {
+                        LineEnding::Lf => LineEnding::CrLf,
+                        LineEnding::CrLf => LineEnding::Lf,
+                    };
+                    buffer.set_line_ending(line_ending);
+                    status.info(format!("line endings: {}", line_ending.name()));
+                }
+                Command::NextTheme => {
+                    theme = themes.next(&theme.name).with(&config.colors);
+                    status.info(format!("theme: {}", theme.name));
+                }
+                Command::ToggleLineNumbers => {
+                    line_numbers = line_numbers.next();
+                    status.info(format!("line numbers: {}", line_numbers.name()));
+                }
+                Command::SplitHorizontal => editor.split(Split::Horizontal),
+                Command::SplitVertical => editor.split(Split::Vertical),
+                Command::ClosePane => editor.close_pane(),
+                Command::NextPane => editor.focus_next(),
+                Command::PrevPane => editor.focus_prev(),
+                Command::GotoLine => {
+                    mode = Mode::GotoLine;
+                    minibuffer.start("");
+                }
+                Command::Palette => {
+                    mode = Mode::Palette;
+                    minibuffer.start("");
+                }
+                Command::Quit => {
+                    quitting = true;
+                    match editor.first_modified() {
+                        Some(i) => {
+                            editor.switch_to(i);
+                            mode = Mode::ConfirmClose;
+                        }
+                        None => quit = true,
+                    }
+                }
+            }
         }
 
-        let cursor_pos = v2!(
-            buffer.cursor.x as f32 * FONT_CHAR_WIDTH as f32 * FONT_SCALE,
-            -(buffer.cursor.y as isize) as f32 * FONT_CHAR_HEIGHT as f32 * FONT_SCALE,
-        );
+        if mode == Mode::Edit && editor.buffer().recovery.is_some() {
+            mode = Mode::Recover;
+        }
 
-        camera_vel = (cursor_pos - camera_pos) * v2s!(2.0);
-        camera_pos += camera_vel * v2s!(DELTA_TIME);
+        editor.sync_views();
 
+        let (width, height) = window.size();
+        let cell_size = glyph_buf.cell_size();
+        // The status line and the minibuffer get a line each at the bottom,
+        // the panes the rest.
+        let line_height = (cell_size.y * height as f32 / config.height as f32).round() as i32;
+        let window_rect = Rect {
+            x: 0,
+            y: 0,
+            w: width as i32,
+            h: (height as i32 - 2 * line_height).max(0),
+        };
+        let status_rect = Rect {
+            y: window_rect.h,
+            h: line_height,
+            ..window_rect
+        };
+        let minibuffer_rect = Rect {
+            y: status_rect.y + line_height,
+            h: height as i32 - status_rect.y - line_height,
+            ..window_rect
+        };
         unsafe {
-            let (width, height) = window.size();
             gl::Viewport(0, 0, width as i32, height as i32);
-            gl::Uniform2f(
-                glyph_buf.resolution_uniform,
-                SCREEN_WIDTH as f32,
-                SCREEN_HEIGHT as f32,
+            gl::Uniform1f(glyph_buf.time_uniform, timer.ticks() as f32 / 1000.0);
+            // What's left of this between panes separates them.
+            let (separator, background) = (theme.separator, theme.background);
+            gl::ClearColor(separator.x, separator.y, separator.z, separator.w);
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+            gl::ClearColor(background.x, background.y, background.z, background.w);
+            gl::Enable(gl::SCISSOR_TEST);
+        }
+
+        for (v, rect) in editor.layout().rects(window_rect) {
+            let focused = v == editor.focused();
+            let cursor = editor.view_cursor(v);
+            let view = editor.view_mut(v);
+            let cursor_pos = v2!(
+                cursor.col as f32 * cell_size.x,
+                -(cursor.y as isize) as f32 * cell_size.y,
             );
-            gl::Uniform2f(glyph_buf.camera_uniform, camera_pos.x, camera_pos.y);
 
-            gl::Uniform1f(glyph_buf.time_uniform, timer.ticks() as f32 / 1000.0);
+            camera_vel = (cursor_pos - view.camera_pos) * v2s!(2.0);
+            view.camera_pos += camera_vel * v2s!(delta_time);
+            let camera_pos = view.camera_pos;
+            let document = editor.views()[v].document;
+            let document = &mut editor.documents_mut()[document];
+            let buffer = &document.buffer;
 
-            gl::Clear(gl::COLOR_BUFFER_BIT);
-            gl::ClearColor(0.0, 0.0, 0.0, 1.0);
+            // The gutter stays at the left of the pane and only scrolls
+            // along with the text vertically.
+            let gutter_width = line_numbers.width(buffer.line_count());
+            let gutter_px = (gutter_width as f32 * cell_size.x * width as f32 / config.width as f32)
+                .round() as i32;
+            let gutter_rect = Rect {
+                w: gutter_px.min(rect.w),
+                ..rect
+            };
+            let rect = Rect {
+                x: rect.x + gutter_rect.w,
+                w: rect.w - gutter_rect.w,
+                ..rect
+            };
+
+            // Panes get their share of the screen at the same scale.
+            let resolution = v2!(
+                config.width as f32 * rect.w as f32 / width as f32,
+                config.height as f32 * rect.h as f32 / height as f32,
+            );
+            glyph_buf.gl_viewport(rect, height as i32, resolution, camera_pos);
+            unsafe {
+                gl::Clear(gl::COLOR_BUFFER_BIT);
+            }
+
+            let lines_per_screen = resolution.y / cell_size.y;
+            let start_idx = cursor.y.saturating_sub(lines_per_screen as usize);
+            let end_idx = std::cmp::min(
+                start_idx + (lines_per_screen * 2.0) as usize + 1,
+                buffer.line_count(),
+            );
+
+            document.highlighter.update(buffer, &grammars, end_idx);
+            let highlighter = &document.highlighter;
+
+            let matches = if focused && matches!(mode, Mode::Search | Mode::Replace) {
+                let visible = buffer.line_start(start_idx)..buffer.line_end(end_idx - 1);
+                search.matches_in(buffer, visible)
+            } else {
+                vec![]
+            };
+
+            glyph_buf.clear();
+            for i in start_idx..end_idx {
+                let line = buffer.line(i);
+                let (line_start, line_end) = (buffer.line_start(i), buffer.line_end(i));
+                // The selection belongs to the focused view.
+                let selection = if focused {
+                    buffer.selection_on_line(i)
+                } else {
+                    None
+                };
+                let mut highlights = vec![];
+                for m in &matches {
+                    let start = std::cmp::max(m.start, line_start) - line_start;
+                    let end = std::cmp::min(m.end, line_end).saturating_sub(line_start);
+                    let overlaps_selection = selection
+                        .as_ref()
+                        .is_some_and(|s| start < s.end && s.start < end);
+                    if start < end && !overlaps_selection {
+                        highlights.push((start..end, theme.background, theme.search_match));
+                    }
+                }
+                if let Some(selection) = selection {
+                    highlights.push((selection, theme.background, theme.selection));
+                    highlights.sort_by_key(|(range, _, _)| range.start);
+                }
+                // Selections and search matches go over the syntax colors.
+                let tokens = highlighter.tokens(i);
+                glyph_buf.render_line_with(&line, v2!(0, -(i as i32)), |at| {
+                    match highlights.iter().find(|(range, _, _)| range.contains(&at)) {
+                        Some(&(_, fg_color, bg_color)) => (fg_color, bg_color),
+                        None => {
+                            let kind = highlight::kind_at(tokens, at);
+                            let fg_color = kind.map_or(theme.text, |kind| theme.token(kind));
+                            (fg_color, theme.background)
+                        }
+                    }
+                });
+            }
+
+            let cursor_color = if focused {
+                theme.cursor
+            } else {
+                theme.inactive_cursor
+            };
+            glyph_buf.gl_render_cursor(buffer, &cursor, theme.background, cursor_color);
+            glyph_buf.sync();
+            glyph_buf.draw();
+
+            if gutter_width > 0 {
+                let resolution = v2!(
+                    config.width as f32 * gutter_rect.w as f32 / width as f32,
+                    resolution.y,
+                );
+                let camera = v2!(resolution.x / 2.0, camera_pos.y);
+                glyph_buf.gl_viewport(gutter_rect, height as i32, resolution, camera);
+                let gutter = theme.gutter;
+                unsafe {
+                    gl::ClearColor(gutter.x, gutter.y, gutter.z, gutter.w);
+                    gl::Clear(gl::COLOR_BUFFER_BIT);
+                    let background = theme.background;
+                    gl::ClearColor(background.x, background.y, background.z, background.w);
+                }
+                glyph_buf.clear();
+                for y in start_idx..end_idx {
+                    let label = line_numbers.label(y, cursor.y, gutter_width);
+                    let color = if y == cursor.y {
+                        theme.gutter_current
+                    } else {
+                        theme.gutter_text
+                    };
+                    glyph_buf.render_line(&label, v2!(0, -(y as i32)), color, gutter);
+                }
+                glyph_buf.sync();
+                glyph_buf.draw();
+            }
         }
 
-        let lines_per_screen = SCREEN_HEIGHT as f32 / (FONT_CHAR_HEIGHT as f32 * FONT_SCALE);
-        let start_idx = (buffer.cursor.y)
-            .checked_sub(lines_per_screen as usize)
-            .unwrap_or(0);
-        let end_idx = std::cmp::min(
-            start_idx + (lines_per_screen * 2.0) as usize,
-            buffer.lines.len(),
+        let buffer = editor.buffer();
+        let prompt = match mode {
+            Mode::Edit if keymap.is_pending() => keymap.pending(),
+            Mode::Edit => vi.as_ref().map(Vi::status).unwrap_or_default(),
+            Mode::Search => search.prompt(false),
+            Mode::Replace => search.prompt(true),
+            Mode::SaveAs => "save as: ".into(),
+            Mode::Open => "open: ".into(),
+            Mode::GotoLine => "go to line: ".into(),
+            Mode::Palette => "run: ".into(),
+            Mode::Buffers => format!("buffers: {}", editor.list()),
+            Mode::ConfirmClose => "unsaved changes: [s]ave, [d]iscard or [c]ancel?".into(),
+            Mode::Recover => format!(
+                "swap file found ({}): [r]ecover or [d]iscard?",
+                buffer
+                    .recovery
+                    .as_ref()
+                    .map_or(String::new(), |recovery| recovery.summary.to_string()),
+            ),
+            Mode::Changed => "file changed on disk: [r]eload or [k]eep your changes?".into(),
+            Mode::Overwrite => {
+                "file changed on disk since: [o]verwrite, [r]eload or [c]ancel?".into()
+            }
+        };
+
+        // Both stay put whatever the camera does.
+        let resolution = v2!(
+            config.width as f32,
+            config.height as f32 * line_height as f32 / height as f32,
         );
+        let columns = (resolution.x / cell_size.x) as usize;
+        let (left, right) = status::describe(buffer, &editor.current().name());
+        let left = format!(" {}  ", left);
+        let right = format!("{} ", right);
+        let message = status.message();
+        let line = status::fit(
+            &format!("{}{}", left, message.map_or("", |(message, _)| message)),
+            &right,
+            columns,
+        );
+        let highlights = match message {
+            Some((message, true)) => {
+                // Whatever of it is left after fitting.
+                let shown = line[..line.len() - right.len()].trim_end().len();
+                let start = left.len().min(shown);
+                let end = (start + message.len()).min(shown);
+                vec![(start..end, theme.error, theme.status_bar)]
+            }
+            _ => vec![],
+        };
+        let camera = v2!(resolution.x / 2.0, cell_size.y / 2.0);
+        glyph_buf.gl_viewport(status_rect, height as i32, resolution, camera);
+        glyph_buf.clear();
+        glyph_buf.render_highlighted_line(
+            &line,
+            v2!(0, 0),
+            theme.status_text,
+            theme.status_bar,
+            &highlights,
+        );
+        glyph_buf.sync();
+        glyph_buf.draw();
 
+        // The prompt goes to the left of where the minibuffer's text starts.
+        let prompt_width = display_width(&prompt) as i32;
+        let input = &minibuffer.input;
+        let scroll = if mode.prompts() {
+            let cursor_x = (prompt_width as usize + input.cursor.col + 1) as f32 * cell_size.x;
+            (cursor_x - resolution.x).max(0.0)
+        } else {
+            0.0
+        };
+        let camera = v2!(
+            resolution.x / 2.0 - prompt_width as f32 * cell_size.x + scroll,
+            cell_size.y / 2.0,
+        );
+        glyph_buf.gl_viewport(minibuffer_rect, height as i32, resolution, camera);
+        unsafe {
+            gl::Clear(gl::COLOR_BUFFER_BIT);
+        }
         glyph_buf.clear();
-        for i in start_idx..end_idx {
-            glyph_buf.render_line(&buffer.lines[i].chars, v2!(0, -(i as i32)), WHITE, BLACK);
+        glyph_buf.render_line(&prompt, v2!(-prompt_width, 0), theme.text, theme.background);
+        if mode.prompts() {
+            let text = input.line(0);
+            glyph_buf.render_line(&text, v2!(0, 0), theme.text, theme.background);
+            glyph_buf.gl_render_cursor(input, &input.cursor, theme.background, theme.cursor);
+            if mode == Mode::Palette {
+                let mut x = display_width(&text) as i32 + 2;
+                for (i, &command) in minibuffer.candidates.iter().enumerate() {
+                    if x as f32 * cell_size.x > resolution.x + scroll {
+                        break;
+                    }
+                    let mut candidate = command.name().to_string();
+                    if let Some(keys) = keymap.keys_for(command).first() {
+                        let keys: Vec<_> = keys.iter().map(Key::to_string).collect();
+                        candidate = format!("{} ({})", candidate, keys.join(" "));
+                    }
+                    let (fg_color, bg_color) = if i == minibuffer.selected {
+                        (theme.background, theme.selection)
+                    } else {
+                        (theme.text, theme.background)
+                    };
+                    glyph_buf.render_line(&candidate, v2!(x, 0), fg_color, bg_color);
+                    x += display_width(&candidate) as i32 + 2;
+                }
+            }
         }
-
-        glyph_buf.gl_render_cursor(&buffer);
         glyph_buf.sync();
         glyph_buf.draw();
 
+        unsafe {
+            gl::Disable(gl::SCISSOR_TEST);
+        }
+
+        let count = editor.documents().len();
+        let title = format!(
+            "{}{}{} - red",
+            if buffer.is_modified() { "*" } else { "" },
+            editor.current().name(),
+            if count > 1 {
+                format!(" [{}/{}]", editor.current_index() + 1, count)
+            } else {
+                String::new()
+            },
+        );
+        if window.title() != title {
+            window.set_title(&title).map_err(|e| e.to_string())?;
+        }
+
         window.gl_swap_window();
 
+        if mode == Mode::Edit {
+            match editor.buffer_mut().poll_disk() {
+                Ok(Some(DiskChange::Reloaded)) => status.info("file changed on disk, reloaded it"),
+                Ok(Some(DiskChange::Conflict)) => mode = Mode::Changed,
+                Ok(None) => {}
+                Err(err) => status.error(err),
+            }
+        }
+
+        if timer.ticks() - last_swap >= SWAP_INTERVAL_MS {
+            last_swap = timer.ticks();
+            for document in editor.documents_mut() {
+                if let Err(err) = document.buffer.write_swap() {
+                    status.error(err);
+                }
+            }
+        }
+
         let duration = timer.ticks() - start;
-        let delta_time_ms = 1000 / FPS;
+        let delta_time_ms = 1000 / config.fps;
         if duration < delta_time_ms {
             timer.delay(delta_time_ms - duration);
         }
     }
 
+    // Whatever wasn't saved by now was deliberately discarded.
+    for document in editor.documents_mut() {
+        document.buffer.remove_swap().map_err(|e| e.to_string())?;
+    }
+
     Ok(())
 }
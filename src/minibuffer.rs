@@ -0,0 +1,171 @@
+use crate::buffer::Buffer;
+use crate::command::{Command, COMMANDS};
+use crate::key::{Key, KeyCode};
+
+// The line at the bottom of the window where things get typed in,
+// like commands to run, paths, line numbers or search queries.
+// It's a `Buffer` of its own that only ever has the one line.
+pub struct Minibuffer {
+    pub input: Buffer,
+    // Commands that go with what's typed so far, best first.
+    pub candidates: Vec<Command>,
+    pub selected: usize,
+}
+
+impl Default for Minibuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Minibuffer {
+    pub fn new() -> Self {
+        Self {
+            input: Buffer::new(),
+            candidates: vec![],
+            selected: 0,
+        }
+    }
+
+    // Starts over with `text` in it and the cursor at the end.
+    pub fn start(&mut self, text: &str) {
+        self.input = Buffer::new();
+        self.insert(text);
+        self.complete();
+    }
+
+    pub fn text(&self) -> String {
+        self.input.line(0).into_owned()
+    }
+
+    pub fn insert(&mut self, text: &str) {
+        let text: String = text
+            .chars()
+            .filter(|&ch| ch != '\n' && ch != '\r')
+            .collect();
+        self.input.insert_text(&text);
+    }
+
+    // Keys that move around or edit the text. Tells whether it changed,
+    // or None for keys that mean something else.
+    pub fn handle(&mut self, key: Key) -> Option<bool> {
+        if key.ctrl || key.alt {
+            return None;
+        }
+        let revision = self.input.revision();
+        match key.code {
+            KeyCode::Left => self.input.move_left(),
+            KeyCode::Right => self.input.move_right(),
+            KeyCode::Home => self.input.set_cursor_offset(0),
+            KeyCode::End => self.input.set_cursor_offset(self.input.len()),
+            KeyCode::Backspace => self.input.backspace(),
+            KeyCode::Delete => self.input.delete(),
+            _ => return None,
+        }
+        Some(self.input.revision() != revision)
+    }
+
+    // Narrows the commands down to the ones that fuzzily match the text.
+    pub fn complete(&mut self) {
+        self.candidates = fuzzy_commands(&self.text());
+        self.selected = 0;
+    }
+
+    pub fn select_next(&mut self) {
+        if !self.candidates.is_empty() {
+            self.selected = (self.selected + 1) % self.candidates.len();
+        }
+    }
+
+    pub fn select_prev(&mut self) {
+        if !self.candidates.is_empty() {
+            let len = self.candidates.len();
+            self.selected = (self.selected + len - 1) % len;
+        }
+    }
+
+    pub fn selection(&self) -> Option<Command> {
+        self.candidates.get(self.selected).copied()
+    }
+}
+
+// How well `pattern` matches `candidate` when its chars show up in order but
+// not necessarily next to each other, or None if they don't. Runs of chars and
+// starts of words count for more, gaps in between count against.
+pub fn fuzzy_score(pattern: &str, candidate: &str) -> Option<i32> {
+    let chars: Vec<char> = candidate.chars().collect();
+    let mut score = 0;
+    let mut prev: Option<usize> = None;
+    let mut i = 0;
+    for p in pattern
+        .chars()
+        .filter(|ch| !ch.is_whitespace())
+        .map(|ch| ch.to_ascii_lowercase())
+    {
+        while chars.get(i)?.to_ascii_lowercase() != p {
+            i += 1;
+        }
+        score += 1;
+        if i == 0 || matches!(chars[i - 1], '.' | '_' | '-' | ' ' | '/') {
+            score += 3;
+        }
+        match prev {
+            Some(prev) if prev + 1 == i => score += 5,
+            Some(prev) => score -= (i - prev - 1).min(3) as i32,
+            None => {}
+        }
+        prev = Some(i);
+        i += 1;
+    }
+    Some(score)
+}
+
+// All the commands when there's no query.
+pub fn fuzzy_commands(query: &str) -> Vec<Command> {
+    let mut scored: Vec<_> = COMMANDS
+        .iter()
+        .filter_map(|&(command, name)| Some((fuzzy_score(query, name)?, name, command)))
+        .collect();
+    if !query.is_empty() {
+        scored.sort_by_key(|&(score, name, _)| (-score, name.len(), name));
+    }
+    scored.into_iter().map(|(_, _, command)| command).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_commands() {
+        assert_eq!(fuzzy_commands("").len(), COMMANDS.len());
+        let found = fuzzy_commands("save");
+        assert_eq!(found[..2], [Command::Save, Command::SaveAs]);
+        assert_eq!(fuzzy_commands("bsas")[0], Command::SaveAs);
+        assert_eq!(fuzzy_commands("PN")[0], Command::NextPane);
+        assert_eq!(fuzzy_commands("zzz"), []);
+        assert!(fuzzy_score("pane", "pane.next") > fuzzy_score("pane", "paste.next"));
+        assert_eq!(fuzzy_score("ab", "ba"), None);
+    }
+
+    #[test]
+    fn test_minibuffer_editing() {
+        let mut minibuffer = Minibuffer::new();
+        minibuffer.start("src/main.rs");
+        assert_eq!(minibuffer.handle(Key::new(KeyCode::Left)), Some(false));
+        minibuffer.insert("x\n");
+        assert_eq!(minibuffer.text(), "src/main.rxs");
+        assert_eq!(minibuffer.handle(Key::new(KeyCode::Home)), Some(false));
+        assert_eq!(minibuffer.handle(Key::new(KeyCode::Delete)), Some(true));
+        assert_eq!(minibuffer.handle(Key::new(KeyCode::Backspace)), Some(false));
+        assert_eq!(minibuffer.text(), "rc/main.rxs");
+        assert_eq!(minibuffer.handle(Key::ctrl(KeyCode::Char('z'))), None);
+        assert_eq!(minibuffer.handle(Key::new(KeyCode::Return)), None);
+
+        minibuffer.start("buf nex");
+        assert_eq!(minibuffer.selection(), Some(Command::NextBuffer));
+        minibuffer.select_prev();
+        minibuffer.select_next();
+        assert_eq!(minibuffer.selection(), Some(Command::NextBuffer));
+    }
+}
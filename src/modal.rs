@@ -0,0 +1,829 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::buffer::Buffer;
+use crate::key::{Key, KeyCode};
+use crate::regex::Text;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViMode {
+    Normal,
+    Insert,
+    Visual { linewise: bool },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordStart,
+    WordBack,
+    WordEnd,
+    LineStart,
+    LineEnd,
+    // `gg` and `G`, unless a count says which line.
+    FirstLine,
+    LastLine,
+    Find { ch: char, forward: bool, till: bool },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InsertAt {
+    Cursor,
+    After,
+    LineStart,
+    LineEnd,
+    LineBelow,
+    LineAbove,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Move(Motion),
+    // Over whole lines without a motion, as in `dd`, or the selection in visual mode.
+    Operate(Operator, Option<Motion>),
+    Insert(InsertAt),
+    Paste { before: bool },
+    Undo,
+    Redo,
+    Repeat,
+    Visual { linewise: bool },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Command {
+    register: Option<char>,
+    count: Option<usize>,
+    action: Action,
+}
+
+impl Command {
+    fn is_change(&self) -> bool {
+        matches!(
+            self.action,
+            Action::Operate(Operator::Delete | Operator::Change, _)
+                | Action::Insert(_)
+                | Action::Paste { .. }
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum ParseError {
+    Incomplete,
+    Invalid,
+}
+
+// Reads a command off the keys typed so far, which is how vi keys read:
+// ["x][count](motion|operator[count](motion|itself)|action)
+fn parse(keys: &[Key], visual: bool) -> Result<Command, ParseError> {
+    let mut keys = keys.iter().copied();
+    let mut next = || keys.next().ok_or(ParseError::Incomplete);
+
+    let mut key = next()?;
+    let mut register = None;
+    if key.char() == Some('"') {
+        register = Some(next()?.char().ok_or(ParseError::Invalid)?);
+        key = next()?;
+    }
+    let (count, key) = parse_count(key, &mut next)?;
+
+    if let Some(motion) = parse_motion(key, &mut next)? {
+        return Ok(Command {
+            register,
+            count,
+            action: Action::Move(motion),
+        });
+    }
+    let operator = |op| Action::Operate(op, None);
+    let action = match (key.char(), key) {
+        (Some('d' | 'x'), _) if visual => operator(Operator::Delete),
+        (Some('c' | 's'), _) if visual => operator(Operator::Change),
+        (Some('y'), _) if visual => operator(Operator::Yank),
+        (Some(ch @ ('d' | 'c' | 'y')), _) => {
+            let op = match ch {
+                'd' => Operator::Delete,
+                'c' => Operator::Change,
+                _ => Operator::Yank,
+            };
+            let (inner_count, key) = parse_count(next()?, &mut next)?;
+            let count = match (count, inner_count) {
+                (Some(a), Some(b)) => Some(a * b),
+                (a, b) => a.or(b),
+            };
+            let action = if key.char() == Some(ch) {
+                Action::Operate(op, None)
+            } else {
+                let motion = parse_motion(key, &mut next)?.ok_or(ParseError::Invalid)?;
+                Action::Operate(op, Some(motion))
+            };
+            return Ok(Command {
+                register,
+                count,
+                action,
+            });
+        }
+        (Some('x'), _)
+        | (
+            None,
+            Key {
+                code: KeyCode::Delete,
+                ..
+            },
+        ) => Action::Operate(Operator::Delete, Some(Motion::Right)),
+        (Some('X'), _) => Action::Operate(Operator::Delete, Some(Motion::Left)),
+        (Some('s'), _) => Action::Operate(Operator::Change, Some(Motion::Right)),
+        (Some('D'), _) => Action::Operate(Operator::Delete, Some(Motion::LineEnd)),
+        (Some('C'), _) => Action::Operate(Operator::Change, Some(Motion::LineEnd)),
+        (Some('Y'), _) => operator(Operator::Yank),
+        (Some('i'), _) => Action::Insert(InsertAt::Cursor),
+        (Some('a'), _) => Action::Insert(InsertAt::After),
+        (Some('I'), _) => Action::Insert(InsertAt::LineStart),
+        (Some('A'), _) => Action::Insert(InsertAt::LineEnd),
+        (Some('o'), _) => Action::Insert(InsertAt::LineBelow),
+        (Some('O'), _) => Action::Insert(InsertAt::LineAbove),
+        (Some('p'), _) => Action::Paste { before: false },
+        (Some('P'), _) => Action::Paste { before: true },
+        (Some('u'), _) => Action::Undo,
+        (Some('.'), _) => Action::Repeat,
+        (Some('v'), _) => Action::Visual { linewise: false },
+        (Some('V'), _) => Action::Visual { linewise: true },
+        (_, key) if key == Key::ctrl(KeyCode::Char('r')) => Action::Redo,
+        _ => return Err(ParseError::Invalid),
+    };
+    Ok(Command {
+        register,
+        count,
+        action,
+    })
+}
+
+fn parse_count(
+    mut key: Key,
+    next: &mut impl FnMut() -> Result<Key, ParseError>,
+) -> Result<(Option<usize>, Key), ParseError> {
+    // A lone 0 is going to the start of the line.
+    if !matches!(key.char(), Some('1'..='9')) {
+        return Ok((None, key));
+    }
+    let mut count = 0usize;
+    while let Some(digit) = key.char().and_then(|ch| ch.to_digit(10)) {
+        count = count.saturating_mul(10).saturating_add(digit as usize);
+        key = next()?;
+    }
+    Ok((Some(count), key))
+}
+
+fn parse_motion(
+    key: Key,
+    next: &mut impl FnMut() -> Result<Key, ParseError>,
+) -> Result<Option<Motion>, ParseError> {
+    let motion = match (key.char(), key.code) {
+        (Some('h'), _) | (None, KeyCode::Left | KeyCode::Backspace) => Motion::Left,
+        (Some('l' | ' '), _) | (None, KeyCode::Right) => Motion::Right,
+        (Some('k'), _) | (None, KeyCode::Up) => Motion::Up,
+        (Some('j'), _) | (None, KeyCode::Down | KeyCode::Return) => Motion::Down,
+        (Some('w'), _) => Motion::WordStart,
+        (Some('b'), _) => Motion::WordBack,
+        (Some('e'), _) => Motion::WordEnd,
+        (Some('0'), _) | (None, KeyCode::Home) => Motion::LineStart,
+        (Some('$'), _) | (None, KeyCode::End) => Motion::LineEnd,
+        (Some('g'), _) => match next()?.char() {
+            Some('g') => Motion::FirstLine,
+            _ => return Err(ParseError::Invalid),
+        },
+        (Some('G'), _) => Motion::LastLine,
+        (Some(find @ ('f' | 'F' | 't' | 'T')), _) => Motion::Find {
+            ch: next()?.char().ok_or(ParseError::Invalid)?,
+            forward: find.is_lowercase(),
+            till: find.eq_ignore_ascii_case(&'t'),
+        },
+        _ => return Ok(None),
+    };
+    Ok(Some(motion))
+}
+
+#[derive(Debug, Clone)]
+struct Register {
+    text: String,
+    linewise: bool,
+}
+
+// Vi keys on top of a `Buffer`. Keys are read into commands, which then
+// get carried out with the same operations the rest of the editor uses.
+#[derive(Debug)]
+pub struct Vi {
+    pub mode: ViMode,
+    // Keys of the command being typed.
+    pending: Vec<Key>,
+    registers: HashMap<char, Register>,
+    // What `.` does again, along with what was typed in insert mode after it.
+    last_change: Option<(Command, Vec<Key>)>,
+    replaying: bool,
+}
+
+impl Default for Vi {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vi {
+    pub fn new() -> Self {
+        Self {
+            mode: ViMode::Normal,
+            pending: vec![],
+            registers: HashMap::new(),
+            last_change: None,
+            replaying: false,
+        }
+    }
+
+    // Something like "-- INSERT --" along with the keys of the command being typed.
+    pub fn status(&self) -> String {
+        let mode = match self.mode {
+            ViMode::Normal => "",
+            ViMode::Insert => "-- INSERT --",
+            ViMode::Visual { linewise: false } => "-- VISUAL --",
+            ViMode::Visual { linewise: true } => "-- VISUAL LINE --",
+        };
+        let pending: String = self.pending.iter().filter_map(Key::char).collect();
+        [mode, &pending]
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    // Whether `key` meant something here, otherwise it's up to the editor.
+    pub fn handle(&mut self, key: Key, buffer: &mut Buffer) -> bool {
+        if self.mode == ViMode::Insert {
+            return self.handle_insert(key, buffer);
+        }
+        let visual = matches!(self.mode, ViMode::Visual { .. });
+        if key.code == KeyCode::Escape {
+            if self.pending.is_empty() && visual {
+                buffer.clear_selection();
+                self.mode = ViMode::Normal;
+            }
+            self.pending.clear();
+            return true;
+        }
+        self.pending.push(key);
+        match parse(&self.pending, visual) {
+            Ok(command) => {
+                self.pending.clear();
+                self.execute(command, buffer);
+                true
+            }
+            Err(ParseError::Incomplete) => true,
+            Err(ParseError::Invalid) => {
+                let started = self.pending.len() > 1;
+                self.pending.clear();
+                started || key.char().is_some()
+            }
+        }
+    }
+
+    fn handle_insert(&mut self, key: Key, buffer: &mut Buffer) -> bool {
+        match (key.char(), key.code) {
+            (_, KeyCode::Escape) => {
+                self.mode = ViMode::Normal;
+                buffer.move_left();
+            }
+            (Some(ch), _) => buffer.insert_text(ch.encode_utf8(&mut [0; 4])),
+            (None, KeyCode::Return) => buffer.newline(),
+            (None, KeyCode::Backspace) => buffer.backspace(),
+            (None, KeyCode::Delete) => buffer.delete(),
+            (None, KeyCode::Left) => buffer.move_left(),
+            (None, KeyCode::Right) => buffer.move_right(),
+            (None, KeyCode::Up) => buffer.move_up(),
+            (None, KeyCode::Down) => buffer.move_down(),
+            _ => return false,
+        }
+        if !self.replaying {
+            if let Some((_, keys)) = &mut self.last_change {
+                keys.push(key);
+            }
+        }
+        true
+    }
+
+    fn execute(&mut self, command: Command, buffer: &mut Buffer) {
+        if command.is_change() && !self.replaying {
+            self.last_change = Some((command, vec![]));
+        }
+        let count = command.count.unwrap_or(1);
+        match command.action {
+            Action::Move(motion) => {
+                apply_motion(motion, command.count, buffer);
+            }
+            Action::Operate(op, motion) => self.operate(op, motion, &command, buffer),
+            Action::Insert(at) => {
+                match at {
+                    InsertAt::Cursor => {}
+                    InsertAt::After => buffer.move_right(),
+                    InsertAt::LineStart => {
+                        buffer.set_cursor_offset(first_non_blank(buffer, buffer.cursor.y))
+                    }
+                    InsertAt::LineEnd => {
+                        buffer.set_cursor_offset(buffer.line_end(buffer.cursor.y));
+                    }
+                    InsertAt::LineBelow => {
+                        buffer.set_cursor_offset(buffer.line_end(buffer.cursor.y));
+                        buffer.newline();
+                    }
+                    InsertAt::LineAbove => {
+                        buffer.set_cursor_offset(buffer.line_start(buffer.cursor.y));
+                        buffer.newline();
+                        buffer.move_up();
+                    }
+                }
+                self.mode = ViMode::Insert;
+            }
+            Action::Paste { before } => self.paste(before, &command, buffer),
+            Action::Undo => (0..count).for_each(|_| buffer.undo()),
+            Action::Redo => (0..count).for_each(|_| buffer.redo()),
+            Action::Repeat => self.repeat(command.count, buffer),
+            Action::Visual { linewise } => {
+                if self.mode == (ViMode::Visual { linewise }) {
+                    buffer.clear_selection();
+                    self.mode = ViMode::Normal;
+                } else {
+                    buffer.start_selection();
+                    self.mode = ViMode::Visual { linewise };
+                }
+            }
+        }
+    }
+
+    fn operate(
+        &mut self,
+        op: Operator,
+        motion: Option<Motion>,
+        command: &Command,
+        buffer: &mut Buffer,
+    ) {
+        let count = command.count.unwrap_or(1);
+        let start = buffer.cursor_offset();
+        let (range, linewise) = match (self.mode, motion) {
+            (ViMode::Visual { linewise }, _) => {
+                let selection = buffer.selection().unwrap_or(start..start);
+                buffer.clear_selection();
+                self.mode = ViMode::Normal;
+                // The char under the cursor is part of the selection too.
+                let end = after_grapheme(buffer, selection.end);
+                if linewise {
+                    let (first, last) = (buffer.line_of(selection.start), buffer.line_of(end));
+                    (first..last, true)
+                } else {
+                    (selection.start..end, false)
+                }
+            }
+            (_, None) => {
+                let y = buffer.cursor.y;
+                (y..std::cmp::min(y + count, buffer.line_count()) - 1, true)
+            }
+            (_, Some(motion)) => {
+                let cursor = buffer.cursor;
+                let y = cursor.y;
+                let moved = apply_motion(motion, command.count, buffer);
+                let end = buffer.cursor_offset();
+                buffer.cursor = cursor;
+                let Some((linewise, inclusive)) = moved else {
+                    return;
+                };
+                let (from, mut to) = (start.min(end), start.max(end));
+                if inclusive {
+                    to = after_grapheme(buffer, to);
+                }
+                // Like `e` rather than `w`, so the space after the word stays.
+                if op == Operator::Change && motion == Motion::WordStart {
+                    to = word_end_range(buffer, start, count);
+                }
+                // Words on the next line aren't part of it.
+                if motion == Motion::WordStart && buffer.line_of(to) > y {
+                    to = std::cmp::max(buffer.line_end(y), from);
+                }
+                if linewise {
+                    (buffer.line_of(from)..buffer.line_of(to), true)
+                } else {
+                    (from..to, false)
+                }
+            }
+        };
+
+        // Lines are turned into the range of text they cover, newline included.
+        let (range, text) = if linewise {
+            let lines = range;
+            let text =
+                buffer.text_range(buffer.line_start(lines.start)..buffer.line_end(lines.end));
+            let text = format!("{}\n", text);
+            let range = if op == Operator::Change {
+                buffer.line_start(lines.start)..buffer.line_end(lines.end)
+            } else {
+                line_span(buffer, lines.start, lines.end)
+            };
+            (range, text)
+        } else {
+            (range.clone(), buffer.text_range(range).into_owned())
+        };
+        self.set_register(command.register, text, linewise);
+
+        match op {
+            Operator::Yank => {
+                if !linewise || motion.is_some() {
+                    buffer.set_cursor_offset(range.start);
+                }
+            }
+            Operator::Delete => {
+                buffer.delete_range(range);
+                if linewise {
+                    let y = buffer.cursor.y;
+                    buffer.set_cursor_offset(first_non_blank(buffer, y));
+                }
+            }
+            Operator::Change => {
+                buffer.delete_range(range);
+                self.mode = ViMode::Insert;
+            }
+        }
+    }
+
+    fn set_register(&mut self, name: Option<char>, text: String, linewise: bool) {
+        let register = Register { text, linewise };
+        if let Some(name) = name.filter(|&name| name != '"') {
+            self.registers.insert(name, register.clone());
+        }
+        self.registers.insert('"', register);
+    }
+
+    fn paste(&mut self, before: bool, command: &Command, buffer: &mut Buffer) {
+        let Some(register) = self.registers.get(&command.register.unwrap_or('"')) else {
+            return;
+        };
+        let text = register.text.repeat(command.count.unwrap_or(1));
+        let y = buffer.cursor.y;
+        if register.linewise {
+            let at = if before {
+                buffer.line_start(y)
+            } else {
+                buffer.line_end(y)
+            };
+            // After the last line there's no newline to put the lines after.
+            let text = if before {
+                text
+            } else {
+                format!("\n{}", &text[..text.len() - 1])
+            };
+            buffer.replace_range(at..at, &text);
+            let y = if before { y } else { y + 1 };
+            buffer.set_cursor_offset(first_non_blank(buffer, y));
+        } else {
+            let at = if before {
+                buffer.cursor_offset()
+            } else {
+                after_grapheme(buffer, buffer.cursor_offset())
+            };
+            buffer.replace_range(at..at, &text);
+            // On the last char pasted.
+            buffer.move_left();
+        }
+    }
+
+    fn repeat(&mut self, count: Option<usize>, buffer: &mut Buffer) {
+        let Some((mut command, keys)) = self.last_change.clone() else {
+            return;
+        };
+        command.count = count.or(command.count);
+        self.replaying = true;
+        self.execute(command, buffer);
+        if self.mode == ViMode::Insert {
+            for key in keys {
+                self.handle_insert(key, buffer);
+            }
+            if self.mode == ViMode::Insert {
+                self.handle_insert(Key::new(KeyCode::Escape), buffer);
+            }
+        }
+        self.replaying = false;
+    }
+}
+
+// Moves the cursor, telling whether the motion is over whole lines and
+// whether it includes where it lands. None when it couldn't go anywhere.
+fn apply_motion(motion: Motion, count: Option<usize>, buffer: &mut Buffer) -> Option<(bool, bool)> {
+    let n = count.unwrap_or(1);
+    let offset = buffer.cursor_offset();
+    let y = buffer.cursor.y;
+    match motion {
+        Motion::Left => (0..n).for_each(|_| buffer.move_left()),
+        Motion::Right => (0..n).for_each(|_| buffer.move_right()),
+        Motion::Up => {
+            (0..n).for_each(|_| buffer.move_up());
+            return Some((true, false));
+        }
+        Motion::Down => {
+            (0..n).for_each(|_| buffer.move_down());
+            return Some((true, false));
+        }
+        Motion::WordStart | Motion::WordBack | Motion::WordEnd => {
+            let text = buffer.text();
+            let target = (0..n).fold(offset, |pos, _| match motion {
+                Motion::WordStart => next_word_start(&text, pos),
+                Motion::WordBack => prev_word_start(&text, pos),
+                _ => next_word_end(&text, pos),
+            });
+            buffer.set_cursor_offset(target);
+            return Some((false, motion == Motion::WordEnd));
+        }
+        Motion::LineStart => buffer.set_cursor_offset(buffer.line_start(y)),
+        Motion::LineEnd => {
+            let y = std::cmp::min(y + n - 1, buffer.line_count() - 1);
+            buffer.set_cursor_offset(buffer.line_end(y));
+        }
+        Motion::FirstLine | Motion::LastLine => {
+            let last = buffer.line_count() - 1;
+            let y = match (motion, count) {
+                (_, Some(n)) => std::cmp::min(n.max(1) - 1, last),
+                (Motion::FirstLine, None) => 0,
+                _ => last,
+            };
+            buffer.set_cursor_offset(first_non_blank(buffer, y));
+            return Some((true, false));
+        }
+        Motion::Find { ch, forward, till } => {
+            let line = buffer.line(y);
+            let x = buffer.cursor.x;
+            let found = if forward {
+                let mut matches = line
+                    .char_indices()
+                    .filter(|&(i, c)| c == ch && i > x + (till as usize));
+                matches
+                    .nth(n - 1)
+                    .map(|(i, _)| if till { prev_char(&line, i) } else { i })
+            } else {
+                let mut matches = line[..x]
+                    .char_indices()
+                    .rev()
+                    .filter(|&(i, c)| c == ch && (!till || i + c.len_utf8() < x));
+                matches
+                    .nth(n - 1)
+                    .map(|(i, c)| if till { i + c.len_utf8() } else { i })
+            };
+            let start = buffer.line_start(y);
+            buffer.set_cursor_offset(start + found?);
+            return Some((false, forward));
+        }
+    }
+    Some((false, false))
+}
+
+fn prev_char(line: &str, i: usize) -> usize {
+    line[..i].char_indices().next_back().map_or(0, |(i, _)| i)
+}
+
+fn after_grapheme(buffer: &mut Buffer, offset: usize) -> usize {
+    let cursor = buffer.cursor;
+    buffer.set_cursor_offset(offset);
+    buffer.move_right();
+    let after = buffer.cursor_offset();
+    buffer.cursor = cursor;
+    after
+}
+
+fn word_end_range(buffer: &mut Buffer, start: usize, count: usize) -> usize {
+    let text = buffer.text();
+    // On whitespace `cw` only changes that.
+    if text.char_at(start).is_some_and(char::is_whitespace) {
+        return next_word_start(&text, start);
+    }
+    // From just before, so a word that's one char long is still a word.
+    let end = (0..count).fold(start, |pos, i| {
+        if i == 0 && is_last_of_word(&text, pos) {
+            pos
+        } else {
+            next_word_end(&text, pos)
+        }
+    });
+    after_grapheme(buffer, end)
+}
+
+fn is_last_of_word(text: &Text, pos: usize) -> bool {
+    let Some(c) = text.char_at(pos) else {
+        return true;
+    };
+    text.char_at(pos + c.len_utf8())
+        .is_none_or(|next| class(next) != class(c))
+}
+
+fn first_non_blank(buffer: &Buffer, y: usize) -> usize {
+    let line = buffer.line(y);
+    let blank = line.len() - line.trim_start_matches([' ', '\t']).len();
+    buffer.line_start(y) + blank
+}
+
+// Lines `first..=last` with one of the newlines around them,
+// so deleting it leaves no empty line behind.
+fn line_span(buffer: &Buffer, first: usize, last: usize) -> Range<usize> {
+    if last + 1 < buffer.line_count() {
+        buffer.line_start(first)..buffer.line_start(last + 1)
+    } else if first > 0 {
+        buffer.line_end(first - 1)..buffer.line_end(last)
+    } else {
+        buffer.line_start(first)..buffer.line_end(last)
+    }
+}
+
+// Whitespace, word chars and anything else.
+fn class(c: char) -> u8 {
+    if c.is_whitespace() {
+        0
+    } else if c.is_alphanumeric() || c == '_' {
+        1
+    } else {
+        2
+    }
+}
+
+fn next_word_start(text: &Text, mut pos: usize) -> usize {
+    let Some(c) = text.char_at(pos) else {
+        return pos;
+    };
+    let start_class = class(c);
+    while let Some(c) = text.char_at(pos) {
+        if start_class == 0 || class(c) != start_class {
+            break;
+        }
+        pos += c.len_utf8();
+    }
+    while let Some(c) = text.char_at(pos) {
+        if class(c) != 0 {
+            break;
+        }
+        pos += c.len_utf8();
+    }
+    pos
+}
+
+fn prev_word_start(text: &Text, mut pos: usize) -> usize {
+    while let Some(c) = text.char_before(pos) {
+        if class(c) != 0 {
+            break;
+        }
+        pos -= c.len_utf8();
+    }
+    let Some(c) = text.char_before(pos) else {
+        return pos;
+    };
+    let word_class = class(c);
+    while let Some(c) = text.char_before(pos) {
+        if class(c) != word_class {
+            break;
+        }
+        pos -= c.len_utf8();
+    }
+    pos
+}
+
+fn next_word_end(text: &Text, mut pos: usize) -> usize {
+    if let Some(c) = text.char_at(pos) {
+        pos += c.len_utf8();
+    }
+    while let Some(c) = text.char_at(pos) {
+        if class(c) != 0 {
+            break;
+        }
+        pos += c.len_utf8();
+    }
+    let Some(c) = text.char_at(pos) else {
+        return pos;
+    };
+    let word_class = class(c);
+    while let Some(c) = text.char_at(pos) {
+        match text.char_at(pos + c.len_utf8()) {
+            Some(next) if class(next) == word_class => pos += c.len_utf8(),
+            _ => break,
+        }
+    }
+    pos
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Escape is written as '\x1b' and Return as '\n'.
+    fn feed(vi: &mut Vi, buffer: &mut Buffer, keys: &str) {
+        for ch in keys.chars() {
+            let key = match ch {
+                '\x1b' => Key::new(KeyCode::Escape),
+                '\n' => Key::new(KeyCode::Return),
+                ch => Key::from(ch),
+            };
+            assert!(vi.handle(key, buffer), "{:?} wasn't handled", key);
+        }
+    }
+
+    fn buffer(text: &str) -> Buffer {
+        let mut b = Buffer::new();
+        b.insert_text(text);
+        b.set_cursor_offset(0);
+        b
+    }
+
+    fn contents(b: &Buffer) -> String {
+        b.text_range(0..b.len()).into_owned()
+    }
+
+    #[test]
+    fn test_vi_motions_and_operators() {
+        let mut vi = Vi::new();
+        let mut b = buffer("one two, three\nfour five");
+        feed(&mut vi, &mut b, "w");
+        assert_eq!(b.cursor_offset(), 4);
+        feed(&mut vi, &mut b, "e");
+        assert_eq!(b.cursor_offset(), 6);
+        feed(&mut vi, &mut b, "b0$");
+        assert_eq!(b.cursor_offset(), 14);
+        feed(&mut vi, &mut b, "0fe");
+        assert_eq!(b.cursor_offset(), 2);
+        feed(&mut vi, &mut b, "2f,");
+        assert_eq!(b.cursor_offset(), 2);
+        feed(&mut vi, &mut b, "t,");
+        assert_eq!(b.cursor_offset(), 6);
+        feed(&mut vi, &mut b, "G");
+        assert_eq!(b.cursor.y, 1);
+        feed(&mut vi, &mut b, "gg");
+        assert_eq!(b.cursor.y, 0);
+
+        feed(&mut vi, &mut b, "dw");
+        assert_eq!(contents(&b), "two, three\nfour five");
+        feed(&mut vi, &mut b, "d2e");
+        assert_eq!(contents(&b), " three\nfour five");
+        feed(&mut vi, &mut b, "3x");
+        assert_eq!(contents(&b), "ree\nfour five");
+        feed(&mut vi, &mut b, "u");
+        assert_eq!(contents(&b), " three\nfour five");
+        feed(&mut vi, &mut b, "jdd");
+        assert_eq!(contents(&b), " three");
+        feed(&mut vi, &mut b, "dt");
+        // Motions that go nowhere change nothing.
+        feed(&mut vi, &mut b, "x");
+        assert_eq!(contents(&b), " three");
+        assert_eq!(vi.status(), "");
+    }
+
+    #[test]
+    fn test_vi_change_and_repeat() {
+        let mut vi = Vi::new();
+        let mut b = buffer("one two three");
+        feed(&mut vi, &mut b, "cwuno\x1b");
+        assert_eq!(contents(&b), "uno two three");
+        assert_eq!(vi.mode, ViMode::Normal);
+        feed(&mut vi, &mut b, "w.");
+        assert_eq!(contents(&b), "uno uno three");
+        feed(&mut vi, &mut b, "w.");
+        assert_eq!(contents(&b), "uno uno uno");
+
+        feed(&mut vi, &mut b, "A!\x1b0.");
+        assert_eq!(contents(&b), "uno uno uno!!");
+        feed(&mut vi, &mut b, "otwo\x1bk0dw");
+        assert_eq!(contents(&b), "uno uno!!\ntwo");
+        feed(&mut vi, &mut b, "2.");
+        assert_eq!(contents(&b), "!!\ntwo");
+        feed(&mut vi, &mut b, "i");
+        assert_eq!(vi.status(), "-- INSERT --");
+    }
+
+    #[test]
+    fn test_vi_registers_and_visual() {
+        let mut vi = Vi::new();
+        let mut b = buffer("one\ntwo\nthree");
+        feed(&mut vi, &mut b, "\"ayyjyyGp");
+        assert_eq!(contents(&b), "one\ntwo\nthree\ntwo");
+        feed(&mut vi, &mut b, "\"aP");
+        assert_eq!(contents(&b), "one\ntwo\nthree\none\ntwo");
+        assert_eq!(b.cursor.y, 3);
+
+        feed(&mut vi, &mut b, "ggvlly");
+        assert_eq!(vi.mode, ViMode::Normal);
+        feed(&mut vi, &mut b, "$p");
+        assert_eq!(contents(&b), "oneone\ntwo\nthree\none\ntwo");
+        feed(&mut vi, &mut b, "jVjd");
+        assert_eq!(contents(&b), "oneone\none\ntwo");
+        feed(&mut vi, &mut b, "vex");
+        assert_eq!(contents(&b), "oneone\n\ntwo");
+        feed(&mut vi, &mut b, "v\x1b");
+        assert_eq!(b.selection(), None);
+    }
+}
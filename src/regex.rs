@@ -0,0 +1,851 @@
+use std::borrow::Cow;
+use std::ops::Range;
+
+// Text as the gap buffer stores it, two slices read as if they were one.
+// Positions are byte offsets into the whole thing.
+#[derive(Clone, Copy)]
+pub struct Text<'a> {
+    front: &'a str,
+    back: &'a str,
+}
+
+impl<'a> Text<'a> {
+    pub fn new(front: &'a str, back: &'a str) -> Self {
+        Self { front, back }
+    }
+    pub fn len(&self) -> usize {
+        self.front.len() + self.back.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    pub fn char_at(&self, pos: usize) -> Option<char> {
+        if pos < self.front.len() {
+            self.front[pos..].chars().next()
+        } else {
+            self.back.get(pos - self.front.len()..)?.chars().next()
+        }
+    }
+    pub fn char_before(&self, pos: usize) -> Option<char> {
+        if pos <= self.front.len() {
+            self.front[..pos].chars().next_back()
+        } else {
+            self.back[..pos - self.front.len()].chars().next_back()
+        }
+    }
+    // Everything before `end`, so a search can't go any further than that.
+    pub fn prefix(&self, end: usize) -> Self {
+        match end.checked_sub(self.front.len()) {
+            None => Self::new(&self.front[..end], ""),
+            Some(back) => Self::new(self.front, &self.back[..back]),
+        }
+    }
+    pub fn slice(&self, range: Range<usize>) -> Cow<'a, str> {
+        let split = self.front.len();
+        if range.end <= split {
+            Cow::Borrowed(&self.front[range])
+        } else if range.start >= split {
+            Cow::Borrowed(&self.back[range.start - split..range.end - split])
+        } else {
+            Cow::Owned([&self.front[range.start..], &self.back[..range.end - split]].concat())
+        }
+    }
+}
+
+impl<'a> From<&'a str> for Text<'a> {
+    fn from(s: &'a str) -> Self {
+        Self::new(s, "")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Perl {
+    Digit,
+    Word,
+    Space,
+}
+
+impl Perl {
+    fn matches(self, c: char) -> bool {
+        match self {
+            Perl::Digit => c.is_ascii_digit(),
+            Perl::Word => is_word(c),
+            Perl::Space => c.is_whitespace(),
+        }
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Range(char, char),
+    Perl(Perl, bool),
+}
+
+#[derive(Debug, Clone)]
+struct Class {
+    items: Vec<ClassItem>,
+    negated: bool,
+}
+
+impl Class {
+    fn perl(perl: Perl, negated: bool) -> Self {
+        Self {
+            items: vec![ClassItem::Perl(perl, false)],
+            negated,
+        }
+    }
+    fn contains(&self, c: char) -> bool {
+        self.items.iter().any(|item| match *item {
+            ClassItem::Range(lo, hi) => lo <= c && c <= hi,
+            ClassItem::Perl(perl, negated) => perl.matches(c) != negated,
+        })
+    }
+    fn matches(&self, c: char, case_insensitive: bool) -> bool {
+        let hit = self.contains(c)
+            || case_insensitive
+                && (c.to_lowercase().any(|c| self.contains(c))
+                    || c.to_uppercase().any(|c| self.contains(c)));
+        hit != self.negated
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Assertion {
+    LineStart,
+    LineEnd,
+    TextStart,
+    TextEnd,
+    WordBoundary,
+    NotWordBoundary,
+}
+
+impl Assertion {
+    fn holds(self, text: Text, pos: usize) -> bool {
+        let before = text.char_before(pos);
+        let after = text.char_at(pos);
+        match self {
+            Assertion::LineStart => before.is_none_or(|c| c == '\n'),
+            Assertion::LineEnd => after.is_none_or(|c| c == '\n'),
+            Assertion::TextStart => pos == 0,
+            Assertion::TextEnd => pos == text.len(),
+            Assertion::WordBoundary | Assertion::NotWordBoundary => {
+                let boundary = before.is_some_and(is_word) != after.is_some_and(is_word);
+                boundary == (self == Assertion::WordBoundary)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Assert(Assertion),
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    },
+}
+
+// Counted repetitions get unrolled, so keep them reasonable.
+const MAX_REPEAT: u32 = 1000;
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    groups: usize,
+}
+
+impl Parser {
+    fn error<T>(&self, msg: &str) -> Result<T, String> {
+        Err(format!("regex error at {}: {}", self.pos, msg))
+    }
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+    fn next(&mut self) -> Result<char, String> {
+        match self.peek() {
+            Some(c) => {
+                self.pos += 1;
+                Ok(c)
+            }
+            None => self.error("unexpected end of pattern"),
+        }
+    }
+
+    fn parse_alt(&mut self) -> Result<Node, String> {
+        let mut alts = vec![self.parse_concat()?];
+        while self.eat('|') {
+            alts.push(self.parse_concat()?);
+        }
+        Ok(if alts.len() == 1 {
+            alts.pop().unwrap()
+        } else {
+            Node::Alt(alts)
+        })
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, String> {
+        let mut nodes = vec![];
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            nodes.push(self.parse_repeat(atom)?);
+        }
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().unwrap(),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    fn parse_repeat(&mut self, mut node: Node) -> Result<Node, String> {
+        loop {
+            let (min, max) = match self.peek() {
+                Some('*') => self.quantifier(0, None),
+                Some('+') => self.quantifier(1, None),
+                Some('?') => self.quantifier(0, Some(1)),
+                Some('{') => match self.parse_counts()? {
+                    Some(counts) => counts,
+                    // Not a repetition, `{` is just a literal then.
+                    None => return Ok(node),
+                },
+                _ => return Ok(node),
+            };
+            if matches!(node, Node::Empty | Node::Assert(_)) {
+                return self.error("nothing to repeat");
+            }
+            let greedy = !self.eat('?');
+            node = Node::Repeat {
+                node: Box::new(node),
+                min,
+                max,
+                greedy,
+            };
+        }
+    }
+
+    fn quantifier(&mut self, min: u32, max: Option<u32>) -> (u32, Option<u32>) {
+        self.pos += 1;
+        (min, max)
+    }
+
+    // Parses `{n}`, `{n,}` or `{n,m}`, leaving the position untouched if it's none of those.
+    fn parse_counts(&mut self) -> Result<Option<(u32, Option<u32>)>, String> {
+        let start = self.pos;
+        self.pos += 1;
+        let min = self.parse_number();
+        let max = if self.eat(',') {
+            self.parse_number()
+        } else {
+            min
+        };
+        if min.is_none() || !self.eat('}') {
+            self.pos = start;
+            return Ok(None);
+        }
+        let min = min.unwrap();
+        if max.is_some_and(|max| max < min) {
+            return self.error("repetition max is smaller than min");
+        }
+        if std::cmp::max(min, max.unwrap_or(0)) > MAX_REPEAT {
+            return self.error("repetition count is too big");
+        }
+        Ok(Some((min, max)))
+    }
+
+    fn parse_number(&mut self) -> Option<u32> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        let digits: String = self.chars[start..self.pos].iter().collect();
+        digits.parse().ok()
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, String> {
+        match self.next()? {
+            '(' => {
+                let index = if self.eat('?') {
+                    if !self.eat(':') {
+                        return self.error("unknown group flag");
+                    }
+                    None
+                } else {
+                    self.groups += 1;
+                    Some(self.groups)
+                };
+                let node = self.parse_alt()?;
+                if !self.eat(')') {
+                    return self.error("unclosed group");
+                }
+                Ok(Node::Group(Box::new(node), index))
+            }
+            '[' => self.parse_class(),
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::Assert(Assertion::LineStart)),
+            '$' => Ok(Node::Assert(Assertion::LineEnd)),
+            '\\' => self.parse_escape(),
+            c @ ('*' | '+' | '?') => self.error(&format!("nothing to repeat with '{c}'")),
+            c => Ok(Node::Char(c)),
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<Node, String> {
+        Ok(match self.next()? {
+            'd' => Node::Class(Class::perl(Perl::Digit, false)),
+            'D' => Node::Class(Class::perl(Perl::Digit, true)),
+            'w' => Node::Class(Class::perl(Perl::Word, false)),
+            'W' => Node::Class(Class::perl(Perl::Word, true)),
+            's' => Node::Class(Class::perl(Perl::Space, false)),
+            'S' => Node::Class(Class::perl(Perl::Space, true)),
+            'b' => Node::Assert(Assertion::WordBoundary),
+            'B' => Node::Assert(Assertion::NotWordBoundary),
+            'A' => Node::Assert(Assertion::TextStart),
+            'z' => Node::Assert(Assertion::TextEnd),
+            c => Node::Char(self.escaped_char(c)?),
+        })
+    }
+
+    fn escaped_char(&self, c: char) -> Result<char, String> {
+        match c {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            c if c.is_alphanumeric() => self.error(&format!("unknown escape '\\{c}'")),
+            c => Ok(c),
+        }
+    }
+
+    fn parse_class(&mut self) -> Result<Node, String> {
+        let negated = self.eat('^');
+        let mut items = vec![];
+        // A ']' right at the start is a literal.
+        let mut first = true;
+        loop {
+            let c = self.next()?;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+            let lo = match c {
+                '\\' => match self.next()? {
+                    'd' => {
+                        items.push(ClassItem::Perl(Perl::Digit, false));
+                        continue;
+                    }
+                    'D' => {
+                        items.push(ClassItem::Perl(Perl::Digit, true));
+                        continue;
+                    }
+                    'w' => {
+                        items.push(ClassItem::Perl(Perl::Word, false));
+                        continue;
+                    }
+                    'W' => {
+                        items.push(ClassItem::Perl(Perl::Word, true));
+                        continue;
+                    }
+                    's' => {
+                        items.push(ClassItem::Perl(Perl::Space, false));
+                        continue;
+                    }
+                    'S' => {
+                        items.push(ClassItem::Perl(Perl::Space, true));
+                        continue;
+                    }
+                    c => self.escaped_char(c)?,
+                },
+                c => c,
+            };
+            let hi = if self.peek() == Some('-') && self.chars.get(self.pos + 1) != Some(&']') {
+                self.pos += 1;
+                match self.next()? {
+                    '\\' => {
+                        let c = self.next()?;
+                        self.escaped_char(c)?
+                    }
+                    c => c,
+                }
+            } else {
+                lo
+            };
+            if hi < lo {
+                return self.error("class range is out of order");
+            }
+            items.push(ClassItem::Range(lo, hi));
+        }
+        Ok(Node::Class(Class { items, negated }))
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Assert(Assertion),
+    // Tries the first branch before the second one.
+    Split(usize, usize),
+    Jmp(usize),
+    Save(usize),
+    Match,
+}
+
+struct Compiler {
+    prog: Vec<Inst>,
+}
+
+impl Compiler {
+    fn emit(&mut self, inst: Inst) -> usize {
+        self.prog.push(inst);
+        self.prog.len() - 1
+    }
+    fn patch_split(&mut self, at: usize, first: usize, second: usize) {
+        self.prog[at] = Inst::Split(first, second);
+    }
+
+    fn compile(&mut self, node: &Node) {
+        match node {
+            Node::Empty => {}
+            Node::Char(c) => {
+                self.emit(Inst::Char(*c));
+            }
+            Node::Any => {
+                self.emit(Inst::Any);
+            }
+            Node::Class(class) => {
+                self.emit(Inst::Class(class.clone()));
+            }
+            Node::Assert(assertion) => {
+                self.emit(Inst::Assert(*assertion));
+            }
+            Node::Group(node, index) => match index {
+                Some(index) => {
+                    self.emit(Inst::Save(2 * index));
+                    self.compile(node);
+                    self.emit(Inst::Save(2 * index + 1));
+                }
+                None => self.compile(node),
+            },
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    self.compile(node);
+                }
+            }
+            Node::Alt(alts) => {
+                let mut jumps = vec![];
+                for (i, alt) in alts.iter().enumerate() {
+                    if i + 1 == alts.len() {
+                        self.compile(alt);
+                        break;
+                    }
+                    let split = self.emit(Inst::Split(0, 0));
+                    self.compile(alt);
+                    jumps.push(self.emit(Inst::Jmp(0)));
+                    let next = self.prog.len();
+                    self.patch_split(split, split + 1, next);
+                }
+                let end = self.prog.len();
+                for jump in jumps {
+                    self.prog[jump] = Inst::Jmp(end);
+                }
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                for _ in 0..*min {
+                    self.compile(node);
+                }
+                let order =
+                    |body: usize, out: usize| if *greedy { (body, out) } else { (out, body) };
+                match max {
+                    None => {
+                        let split = self.emit(Inst::Split(0, 0));
+                        self.compile(node);
+                        self.emit(Inst::Jmp(split));
+                        let (first, second) = order(split + 1, self.prog.len());
+                        self.patch_split(split, first, second);
+                    }
+                    Some(max) => {
+                        let mut splits = vec![];
+                        for _ in *min..*max {
+                            splits.push(self.emit(Inst::Split(0, 0)));
+                            self.compile(node);
+                        }
+                        let out = self.prog.len();
+                        for split in splits {
+                            let (first, second) = order(split + 1, out);
+                            self.patch_split(split, first, second);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Captures {
+    slots: Vec<Option<usize>>,
+}
+
+impl Captures {
+    pub fn get(&self, group: usize) -> Option<Range<usize>> {
+        let start = (*self.slots.get(2 * group)?)?;
+        let end = (*self.slots.get(2 * group + 1)?)?;
+        Some(start..end)
+    }
+    // The whole match.
+    pub fn range(&self) -> Range<usize> {
+        self.get(0).expect("group 0 is always set on a match")
+    }
+}
+
+// Threads of the VM for a given position, kept in priority order.
+struct Threads {
+    list: Vec<(usize, Vec<Option<usize>>)>,
+    seen: Vec<bool>,
+}
+
+impl Threads {
+    fn new(len: usize) -> Self {
+        Self {
+            list: vec![],
+            seen: vec![false; len],
+        }
+    }
+    fn clear(&mut self) {
+        self.list.clear();
+        self.seen.fill(false);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Regex {
+    prog: Vec<Inst>,
+    groups: usize,
+    case_insensitive: bool,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Self, String> {
+        match pattern.strip_prefix("(?i)") {
+            Some(pattern) => Self::with_case(pattern, true),
+            None => Self::with_case(pattern, false),
+        }
+    }
+
+    pub fn with_case(pattern: &str, case_insensitive: bool) -> Result<Self, String> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+            groups: 0,
+        };
+        let node = parser.parse_alt()?;
+        if parser.pos < parser.chars.len() {
+            return parser.error("unmatched ')'");
+        }
+        let mut compiler = Compiler { prog: vec![] };
+        compiler.emit(Inst::Save(0));
+        compiler.compile(&node);
+        compiler.emit(Inst::Save(1));
+        compiler.emit(Inst::Match);
+        Ok(Self {
+            prog: compiler.prog,
+            groups: parser.groups + 1,
+            case_insensitive,
+        })
+    }
+
+    // Leftmost match starting at or after `from`, preferring the
+    // alternatives and repetitions the way a backtracking engine would.
+    pub fn find_at(&self, text: Text, from: usize) -> Option<Captures> {
+        self.run(text, from, false)
+    }
+
+    // Like `find_at`, but only a match starting right at `at` will do.
+    pub fn match_at(&self, text: Text, at: usize) -> Option<Captures> {
+        self.run(text, at, true)
+    }
+
+    fn run(&self, text: Text, from: usize, anchored: bool) -> Option<Captures> {
+        let mut clist = Threads::new(self.prog.len());
+        let mut nlist = Threads::new(self.prog.len());
+        let mut caps = vec![None; 2 * self.groups];
+        let mut matched = None;
+        let mut pos = from;
+        loop {
+            if matched.is_none() && !(anchored && pos > from) {
+                self.add_thread(&mut clist, 0, text, pos, &mut caps);
+            }
+            if clist.list.is_empty() && (matched.is_some() || anchored) {
+                break;
+            }
+            let c = text.char_at(pos);
+            let next = pos + c.map_or(0, char::len_utf8);
+            for (pc, thread_caps) in std::mem::take(&mut clist.list) {
+                let step = match (&self.prog[pc], c) {
+                    (Inst::Match, _) => {
+                        matched = Some(thread_caps);
+                        // Everything left has lower priority.
+                        break;
+                    }
+                    (Inst::Char(ch), Some(c)) => {
+                        *ch == c || self.case_insensitive && eq_ignore_case(*ch, c)
+                    }
+                    (Inst::Any, Some(c)) => c != '\n',
+                    (Inst::Class(class), Some(c)) => class.matches(c, self.case_insensitive),
+                    _ => false,
+                };
+                if step {
+                    let mut thread_caps = thread_caps;
+                    self.add_thread(&mut nlist, pc + 1, text, next, &mut thread_caps);
+                }
+            }
+            if c.is_none() {
+                break;
+            }
+            pos = next;
+            std::mem::swap(&mut clist, &mut nlist);
+            nlist.clear();
+        }
+        matched.map(|slots| Captures { slots })
+    }
+
+    fn add_thread(
+        &self,
+        threads: &mut Threads,
+        pc: usize,
+        text: Text,
+        pos: usize,
+        caps: &mut Vec<Option<usize>>,
+    ) {
+        if threads.seen[pc] {
+            return;
+        }
+        threads.seen[pc] = true;
+        match self.prog[pc] {
+            Inst::Jmp(to) => self.add_thread(threads, to, text, pos, caps),
+            Inst::Split(first, second) => {
+                self.add_thread(threads, first, text, pos, caps);
+                self.add_thread(threads, second, text, pos, caps);
+            }
+            Inst::Save(slot) => {
+                let old = caps[slot].replace(pos);
+                self.add_thread(threads, pc + 1, text, pos, caps);
+                caps[slot] = old;
+            }
+            Inst::Assert(assertion) => {
+                if assertion.holds(text, pos) {
+                    self.add_thread(threads, pc + 1, text, pos, caps);
+                }
+            }
+            _ => threads.list.push((pc, caps.clone())),
+        }
+    }
+
+    pub fn find_iter<'r, 't>(&'r self, text: Text<'t>) -> Matches<'r, 't> {
+        self.find_iter_at(text, 0)
+    }
+
+    pub fn find_iter_at<'r, 't>(&'r self, text: Text<'t>, from: usize) -> Matches<'r, 't> {
+        Matches {
+            regex: self,
+            text,
+            pos: from,
+            last_end: None,
+        }
+    }
+
+    // Expands `$0`, `$1`, `${1}` and `$$` in `replacement` with what `caps` matched.
+    pub fn expand(&self, caps: &Captures, text: Text, replacement: &str) -> String {
+        let mut out = String::new();
+        let mut rest = replacement;
+        while let Some(i) = rest.find('$') {
+            out.push_str(&rest[..i]);
+            rest = &rest[i + 1..];
+            if let Some(after) = rest.strip_prefix('$') {
+                out.push('$');
+                rest = after;
+                continue;
+            }
+            let (digits, after) = match rest.strip_prefix('{') {
+                Some(braced) => match braced.find('}') {
+                    Some(end) => (&braced[..end], &braced[end + 1..]),
+                    None => ("", rest),
+                },
+                None => {
+                    let end = rest
+                        .find(|c: char| !c.is_ascii_digit())
+                        .unwrap_or(rest.len());
+                    (&rest[..end], &rest[end..])
+                }
+            };
+            match digits.parse::<usize>() {
+                Ok(group) => {
+                    if let Some(range) = caps.get(group) {
+                        out.push_str(&text.slice(range));
+                    }
+                    rest = after;
+                }
+                Err(_) => out.push('$'),
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+fn eq_ignore_case(a: char, b: char) -> bool {
+    a.to_lowercase().eq(b.to_lowercase())
+}
+
+pub struct Matches<'r, 't> {
+    regex: &'r Regex,
+    text: Text<'t>,
+    pos: usize,
+    last_end: Option<usize>,
+}
+
+impl Iterator for Matches<'_, '_> {
+    type Item = Captures;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.pos > self.text.len() {
+                return None;
+            }
+            let caps = self.regex.find_at(self.text, self.pos)?;
+            let range = caps.range();
+            // An empty match right where the last one ended is skipped,
+            // otherwise patterns like `a*` would never move forward.
+            if range.is_empty() && self.last_end == Some(range.end) {
+                self.pos = range.end + self.text.char_at(range.end)?.len_utf8();
+                continue;
+            }
+            self.pos = range.end;
+            self.last_end = Some(range.end);
+            return Some(caps);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(pattern: &str, text: &str) -> Option<Range<usize>> {
+        Regex::new(pattern)
+            .unwrap()
+            .find_at(text.into(), 0)
+            .map(|caps| caps.range())
+    }
+
+    fn find_all(pattern: &str, text: &str) -> Vec<Range<usize>> {
+        Regex::new(pattern)
+            .unwrap()
+            .find_iter(text.into())
+            .map(|caps| caps.range())
+            .collect()
+    }
+
+    #[test]
+    fn test_regex_basics() {
+        assert_eq!(find("abc", "xxabcxx"), Some(2..5));
+        assert_eq!(find("a.c", "abc a\nc"), Some(0..3));
+        assert_eq!(find("a.c", "a\nc"), None);
+        assert_eq!(find("colou?r", "color colour"), Some(0..5));
+        assert_eq!(find("ab+", "a ab abbb"), Some(2..4));
+        assert_eq!(find("[0-9]+", "port = 8080"), Some(7..11));
+        assert_eq!(find("[^a-z ]+", "key = VALUE"), Some(4..5));
+        assert_eq!(find("\\d{2,3}", "1 12345"), Some(2..5));
+        assert_eq!(find("x{2}", "x xx"), Some(2..4));
+        assert_eq!(find("a{,2}", "a{,2}"), Some(0..5));
+        assert_eq!(find("\\bcat\\b", "concat cat"), Some(7..10));
+        assert_eq!(find("\\w+", "  \u{e9}t\u{e9} "), Some(2..7));
+        assert_eq!(find("(?i)hello", "say HeLLo"), Some(4..9));
+        assert_eq!(find("[a-c]+", "xyzABC"), None);
+        assert_eq!(find("(?i)[a-c]+", "xyzABC"), Some(3..6));
+
+        let re = Regex::new("\\w+").unwrap();
+        assert_eq!(
+            re.match_at("ab cd".into(), 3).map(|c| c.range()),
+            Some(3..5)
+        );
+        assert_eq!(re.match_at("ab cd".into(), 2).map(|c| c.range()), None);
+    }
+
+    #[test]
+    fn test_regex_priorities() {
+        assert_eq!(find("a|ab", "ab"), Some(0..1));
+        assert_eq!(find("ab|a", "ab"), Some(0..2));
+        assert_eq!(find("<.+>", "<a><b>"), Some(0..6));
+        assert_eq!(find("<.+?>", "<a><b>"), Some(0..3));
+        assert_eq!(find("(a*)*b", "aaab"), Some(0..4));
+        assert_eq!(find_all("a*", "baaa"), [0..0, 1..4]);
+        assert_eq!(find_all("", "ab"), [0..0, 1..1, 2..2]);
+    }
+
+    #[test]
+    fn test_regex_anchors_across_lines() {
+        assert_eq!(find_all("^\\w+", "one\ntwo\n three"), [0..3, 4..7]);
+        assert_eq!(find_all("\\w+$", "one\ntwo \nthree"), [0..3, 9..14]);
+        assert_eq!(find("e\\ntw", "one\ntwo"), Some(2..6));
+        assert_eq!(find("\\Aone", "one\none"), Some(0..3));
+        assert_eq!(find("one\\z", "one\none"), Some(4..7));
+        assert_eq!(find("e\\s+t", "one \n\ttwo"), Some(2..7));
+    }
+
+    #[test]
+    fn test_regex_over_split_text() {
+        let re = Regex::new("(\\w+) = (\\w+)").unwrap();
+        let text = Text::new("name = re", "d\nlang = rust");
+        let all: Vec<_> = re.find_iter(text).collect();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].range(), 0..10);
+        assert_eq!(text.slice(all[0].get(2).unwrap()), "red");
+        assert_eq!(re.expand(&all[0], text, "$2: $1"), "red: name");
+        assert_eq!(re.expand(&all[1], text, "${2}y $$1 $3"), "rusty $1 ");
+
+        let re = Regex::new("d\\nl").unwrap();
+        assert_eq!(re.find_at(text, 0).map(|c| c.range()), Some(9..12));
+        assert_eq!(text.char_before(9), Some('e'));
+        assert_eq!(text.char_before(10), Some('d'));
+    }
+
+    #[test]
+    fn test_regex_errors() {
+        assert!(Regex::new("(abc").is_err());
+        assert!(Regex::new("abc)").is_err());
+        assert!(Regex::new("*a").is_err());
+        assert!(Regex::new("[z-a]").is_err());
+        assert!(Regex::new("a{3,2}").is_err());
+        assert!(Regex::new("\\q").is_err());
+        assert!(Regex::new("[abc").is_err());
+    }
+}
@@ -0,0 +1,445 @@
+use std::ops::Range;
+
+use crate::buffer::Buffer;
+use crate::regex::Regex;
+
+// Where the line `offset` is in ends, past its '\n'. Regex searches stop
+// there, otherwise not finding a match means going all the way to the end.
+fn after_line(buffer: &Buffer, offset: usize) -> usize {
+    let end = buffer.line_end(buffer.line_of(offset));
+    (end + 1).min(buffer.len())
+}
+
+// The last regex match that starts before `before`. It looks at more and more
+// lines above it, twice as many each time, so how much gets scanned depends on
+// how far back the match is rather than on how long the buffer is.
+fn rfind_regex(re: &Regex, buffer: &Buffer, before: usize) -> Option<Range<usize>> {
+    let text = buffer.text().prefix(after_line(buffer, before));
+    let mut top = buffer.line_of(before) + 1;
+    let mut end = before;
+    let mut lines = 1;
+    loop {
+        let first = top.saturating_sub(lines);
+        let start = buffer.line_start(first);
+        let last = re
+            .find_iter_at(text, start)
+            .map(|caps| caps.range())
+            .take_while(|m| m.start < end)
+            .last();
+        if last.is_some() || first == 0 {
+            return last;
+        }
+        (top, end, lines) = (first, start, lines * 2);
+    }
+}
+
+// Matches of `query` inside `line`, left to right and not overlapping.
+pub fn find_in_line(line: &str, query: &str, case_insensitive: bool) -> Vec<Range<usize>> {
+    let mut matches = vec![];
+    if query.is_empty() {
+        return matches;
+    }
+    let mut from = 0;
+    while let Some(range) = find_at(line, from, query, case_insensitive) {
+        from = range.end;
+        matches.push(range);
+    }
+    matches
+}
+
+fn find_at(line: &str, from: usize, query: &str, case_insensitive: bool) -> Option<Range<usize>> {
+    if !case_insensitive {
+        return line[from..]
+            .find(query)
+            .map(|i| from + i..from + i + query.len());
+    }
+    line[from..].char_indices().find_map(|(i, _)| {
+        let start = from + i;
+        let mut hay = line[start..].char_indices();
+        for q in query.chars() {
+            let (_, h) = hay.next()?;
+            if !eq_ignore_case(h, q) {
+                return None;
+            }
+        }
+        let end = hay.next().map_or(line.len(), |(i, _)| start + i);
+        Some(start..end)
+    })
+}
+
+fn eq_ignore_case(a: char, b: char) -> bool {
+    a == b || a.to_lowercase().eq(b.to_lowercase())
+}
+
+// Next match starting at or after `from`, wrapping around the end of the buffer.
+pub fn find_next(
+    buffer: &Buffer,
+    from: usize,
+    query: &str,
+    case_insensitive: bool,
+) -> Option<Range<usize>> {
+    let first = buffer.line_of(from);
+    let lines = buffer.line_count();
+    // The first line is visited twice, the second time for matches before `from`.
+    (0..=lines).find_map(|i| {
+        let y = (first + i) % lines;
+        let start = buffer.line_start(y);
+        find_in_line(&buffer.line(y), query, case_insensitive)
+            .into_iter()
+            .map(|m| start + m.start..start + m.end)
+            .find(|m| i != 0 || m.start >= from)
+    })
+}
+
+// Last match starting before `before`, wrapping around the start of the buffer.
+pub fn find_prev(
+    buffer: &Buffer,
+    before: usize,
+    query: &str,
+    case_insensitive: bool,
+) -> Option<Range<usize>> {
+    let first = buffer.line_of(before);
+    let lines = buffer.line_count();
+    (0..=lines).find_map(|i| {
+        let y = (first + lines - i % lines) % lines;
+        let start = buffer.line_start(y);
+        find_in_line(&buffer.line(y), query, case_insensitive)
+            .into_iter()
+            .map(|m| start + m.start..start + m.end)
+            .rfind(|m| i != 0 || m.start < before)
+    })
+}
+
+pub fn find_all(buffer: &Buffer, query: &str, case_insensitive: bool) -> Vec<Range<usize>> {
+    (0..buffer.line_count())
+        .flat_map(|y| {
+            let start = buffer.line_start(y);
+            find_in_line(&buffer.line(y), query, case_insensitive)
+                .into_iter()
+                .map(move |m| start + m.start..start + m.end)
+        })
+        .collect()
+}
+
+#[derive(Default)]
+pub struct Search {
+    pub query: String,
+    pub replacement: String,
+    pub case_insensitive: bool,
+    // Whether `query` is a regex and `replacement` can refer to its groups.
+    pub regex: bool,
+    // Cursor offset when the search started,
+    // the query is looked up from here again every time it changes.
+    origin: usize,
+    // Match the cursor is on, if any.
+    current: Option<Range<usize>>,
+    compiled: Option<Result<Regex, String>>,
+    pub failing: bool,
+}
+
+impl Search {
+    pub fn start(&mut self, buffer: &Buffer) {
+        self.origin = buffer.cursor_offset();
+        self.query.clear();
+        self.current = None;
+        self.failing = false;
+    }
+
+    // Has to be called every time the query or the flags change.
+    pub fn update(&mut self, buffer: &mut Buffer) {
+        self.compiled = self
+            .regex
+            .then(|| Regex::with_case(&self.query, self.case_insensitive));
+        let found = self.find_next(buffer, self.origin);
+        if found.is_none() {
+            buffer.set_cursor_offset(self.origin);
+        }
+        self.jump(buffer, found);
+    }
+
+    fn compiled_regex(&self) -> Option<&Regex> {
+        self.compiled.as_ref()?.as_ref().ok()
+    }
+
+    fn is_searchable(&self) -> bool {
+        !self.query.is_empty() && (!self.regex || self.compiled_regex().is_some())
+    }
+
+    fn find_next(&self, buffer: &Buffer, from: usize) -> Option<Range<usize>> {
+        if !self.is_searchable() {
+            return None;
+        }
+        match self.compiled_regex() {
+            Some(re) => re
+                .find_at(buffer.text(), from)
+                .or_else(|| re.find_at(buffer.text(), 0))
+                .map(|caps| caps.range()),
+            None => find_next(buffer, from, &self.query, self.case_insensitive),
+        }
+    }
+
+    fn find_prev(&self, buffer: &Buffer, before: usize) -> Option<Range<usize>> {
+        if !self.is_searchable() {
+            return None;
+        }
+        match self.compiled_regex() {
+            Some(re) => {
+                rfind_regex(re, buffer, before).or_else(|| rfind_regex(re, buffer, buffer.len()))
+            }
+            None => find_prev(buffer, before, &self.query, self.case_insensitive),
+        }
+    }
+
+    // Matches that start inside `range`, for highlighting what's on screen.
+    pub fn matches_in(&self, buffer: &Buffer, range: Range<usize>) -> Vec<Range<usize>> {
+        if !self.is_searchable() {
+            return vec![];
+        }
+        match self.compiled_regex() {
+            Some(re) => {
+                let text = buffer.text().prefix(after_line(buffer, range.end));
+                re.find_iter_at(text, range.start)
+                    .map(|caps| caps.range())
+                    .take_while(|m| m.start < range.end)
+                    .collect()
+            }
+            None => (buffer.line_of(range.start)..=buffer.line_of(range.end))
+                .flat_map(|y| {
+                    let start = buffer.line_start(y);
+                    find_in_line(&buffer.line(y), &self.query, self.case_insensitive)
+                        .into_iter()
+                        .map(move |m| start + m.start..start + m.end)
+                })
+                .collect(),
+        }
+    }
+
+    pub fn next(&mut self, buffer: &mut Buffer) {
+        let from = match &self.current {
+            Some(current) => {
+                let step = buffer
+                    .text()
+                    .char_at(current.start)
+                    .map_or(1, char::len_utf8);
+                current.start + step
+            }
+            None => buffer.cursor_offset(),
+        };
+        let found = self.find_next(buffer, from);
+        self.jump(buffer, found);
+    }
+
+    pub fn prev(&mut self, buffer: &mut Buffer) {
+        let before = self
+            .current
+            .as_ref()
+            .map_or(buffer.cursor_offset(), |current| current.start);
+        let found = self.find_prev(buffer, before);
+        self.jump(buffer, found);
+    }
+
+    fn jump(&mut self, buffer: &mut Buffer, found: Option<Range<usize>>) {
+        self.failing = found.is_none() && !self.query.is_empty();
+        match &found {
+            Some(range) => buffer.select(range.clone()),
+            None => buffer.clear_selection(),
+        }
+        self.current = found;
+    }
+
+    // The current match and what it should be replaced with,
+    // as long as the cursor is still sitting on it.
+    fn current_replacement(&self, buffer: &Buffer) -> Option<(Range<usize>, String)> {
+        let current = self.current.clone()?;
+        if buffer.cursor_offset() != current.end {
+            return None;
+        }
+        match self.compiled_regex() {
+            Some(re) => {
+                let text = buffer.text();
+                let caps = re.find_at(text, current.start)?;
+                (caps.range() == current)
+                    .then(|| (current, re.expand(&caps, text, &self.replacement)))
+            }
+            None => {
+                let text = buffer.text_range(current.clone());
+                let matches = find_in_line(&text, &self.query, self.case_insensitive);
+                (matches.first() == Some(&(0..text.len())))
+                    .then(|| (current, self.replacement.clone()))
+            }
+        }
+    }
+
+    pub fn replace(&mut self, buffer: &mut Buffer) {
+        if let Some((range, text)) = self.current_replacement(buffer) {
+            buffer.replace_range(range, &text);
+        }
+        let found = self.find_next(buffer, buffer.cursor_offset());
+        self.jump(buffer, found);
+    }
+
+    pub fn replace_all(&mut self, buffer: &mut Buffer) -> usize {
+        if !self.is_searchable() {
+            return 0;
+        }
+        let replacements: Vec<_> = match self.compiled_regex() {
+            Some(re) => {
+                let text = buffer.text();
+                re.find_iter(text)
+                    .map(|caps| (caps.range(), re.expand(&caps, text, &self.replacement)))
+                    .collect()
+            }
+            None => find_all(buffer, &self.query, self.case_insensitive)
+                .into_iter()
+                .map(|range| (range, self.replacement.clone()))
+                .collect(),
+        };
+        buffer.replace_ranges(&replacements);
+        self.current = None;
+        self.failing = false;
+        replacements.len()
+    }
+
+    // What the minibuffer shows in front of the query being typed,
+    // or of the replacement when `replacing`.
+    pub fn prompt(&self, replacing: bool) -> String {
+        let mut prompt = String::new();
+        if self.failing {
+            prompt.push_str("failing ");
+        }
+        prompt.push_str(if self.regex { "regex search" } else { "search" });
+        if self.case_insensitive {
+            prompt.push_str(" [i]");
+        }
+        if let Some(Err(err)) = &self.compiled {
+            prompt.push_str(" (");
+            prompt.push_str(err);
+            prompt.push(')');
+        }
+        prompt.push_str(": ");
+        if replacing {
+            prompt.push_str(&self.query);
+            prompt.push_str(" -> ");
+        }
+        prompt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer_with(text: &str) -> Buffer {
+        let mut buffer = Buffer::new();
+        buffer.insert_text(text);
+        buffer.set_cursor_offset(0);
+        buffer
+    }
+
+    #[test]
+    fn test_find_in_line() {
+        assert_eq!(find_in_line("abcabc", "bc", false), [1..3, 4..6]);
+        assert_eq!(find_in_line("aaaa", "aa", false), [0..2, 2..4]);
+        assert_eq!(find_in_line("Foo fOO", "foo", false), []);
+        assert_eq!(find_in_line("Foo fOO", "foo", true), [0..3, 4..7]);
+        assert_eq!(find_in_line("\u{c9}t\u{c9}", "\u{e9}", true), [0..2, 3..5]);
+        assert_eq!(find_in_line("abc", "", false), []);
+    }
+
+    #[test]
+    fn test_find_next_and_prev_wrap_around() {
+        let buffer = buffer_with("key = 1\nother = 2\nkey = 3");
+        assert_eq!(find_next(&buffer, 0, "key", false), Some(0..3));
+        assert_eq!(find_next(&buffer, 1, "key", false), Some(18..21));
+        assert_eq!(find_next(&buffer, 19, "key", false), Some(0..3));
+        assert_eq!(find_next(&buffer, 19, "nope", false), None);
+
+        assert_eq!(find_prev(&buffer, 18, "key", false), Some(0..3));
+        assert_eq!(find_prev(&buffer, 0, "key", false), Some(18..21));
+        assert_eq!(find_prev(&buffer, 10, "=", false), Some(4..5));
+    }
+
+    #[test]
+    fn test_search_and_replace() {
+        let mut buffer = buffer_with("a.b\nA.b\na.b");
+        let mut search = Search::default();
+        search.start(&buffer);
+        search.query.push('a');
+        search.update(&mut buffer);
+        assert_eq!(buffer.selection(), Some(0..1));
+        search.next(&mut buffer);
+        assert_eq!(buffer.selection(), Some(8..9));
+        search.case_insensitive = true;
+        search.prev(&mut buffer);
+        assert_eq!(buffer.selection(), Some(4..5));
+
+        search.replacement.push_str("xy");
+        search.replace(&mut buffer);
+        assert_eq!(buffer.text_range(0..buffer.len()), "a.b\nxy.b\na.b");
+        assert_eq!(buffer.selection(), Some(9..10));
+
+        assert_eq!(search.replace_all(&mut buffer), 2);
+        assert_eq!(buffer.text_range(0..buffer.len()), "xy.b\nxy.b\nxy.b");
+        buffer.undo();
+        assert_eq!(buffer.text_range(0..buffer.len()), "a.b\nxy.b\na.b");
+    }
+
+    #[test]
+    fn test_regex_search_and_replace() {
+        let mut buffer = buffer_with("fn a(x: u8)\nfn bb(y: u16)\n");
+        let mut search = Search {
+            regex: true,
+            ..Default::default()
+        };
+        search.start(&buffer);
+        search.query.push_str("(\\w+): (\\w+)");
+        search.update(&mut buffer);
+        assert_eq!(buffer.selection(), Some(5..10));
+        search.next(&mut buffer);
+        assert_eq!(buffer.selection(), Some(18..24));
+        search.next(&mut buffer);
+        assert_eq!(buffer.selection(), Some(5..10));
+        search.prev(&mut buffer);
+        assert_eq!(buffer.selection(), Some(18..24));
+        assert_eq!(search.matches_in(&buffer, 0..12), vec![5..10]);
+
+        search.replacement.push_str("$2 $1");
+        search.replace(&mut buffer);
+        assert_eq!(
+            buffer.text_range(0..buffer.len()),
+            "fn a(x: u8)\nfn bb(u16 y)\n"
+        );
+
+        search.query = "\\)\\nfn".to_string();
+        search.replacement = ") ->".to_string();
+        search.update(&mut buffer);
+        assert_eq!(search.replace_all(&mut buffer), 1);
+        assert_eq!(
+            buffer.text_range(0..buffer.len()),
+            "fn a(x: u8) -> bb(u16 y)\n"
+        );
+
+        search.query.push('(');
+        search.update(&mut buffer);
+        assert!(search.failing);
+        assert_eq!(search.replace_all(&mut buffer), 0);
+    }
+
+    #[test]
+    fn test_regex_search_stays_near_the_cursor() {
+        let buffer = buffer_with("a\nb\nxa\nb\nx\nx\nx\nx\na\n");
+        let search = Search {
+            regex: true,
+            query: "a\\nb".to_string(),
+            compiled: Some(Regex::new("a\\nb")),
+            ..Default::default()
+        };
+        // Found a few windows up, even when it goes on into the next line.
+        assert_eq!(search.find_prev(&buffer, 15), Some(5..8));
+        assert_eq!(search.find_prev(&buffer, 5), Some(0..3));
+        assert_eq!(search.find_prev(&buffer, 0), Some(5..8));
+        // Only what starts on screen, though it may end past it.
+        assert_eq!(search.matches_in(&buffer, 4..7), vec![5..8]);
+        assert_eq!(search.matches_in(&buffer, 8..18), vec![]);
+    }
+}
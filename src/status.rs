@@ -0,0 +1,118 @@
+use std::fmt::Display;
+
+use crate::buffer::{display_width, Buffer};
+
+// What the status line has to say besides describing the buffer,
+// which lasts until the next key is pressed.
+#[derive(Default)]
+pub struct Status {
+    message: Option<(String, bool)>,
+}
+
+impl Status {
+    pub fn info(&mut self, message: impl Into<String>) {
+        self.message = Some((message.into(), false));
+    }
+
+    // Errors pile up until the status is cleared, like the ones at startup,
+    // so none of them goes unseen.
+    pub fn error(&mut self, err: impl Display) {
+        let err = err.to_string().replace('\n', "; ");
+        match &mut self.message {
+            Some((message, true)) => {
+                message.push_str("; ");
+                message.push_str(&err);
+            }
+            _ => self.message = Some((err, true)),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.message = None;
+    }
+
+    // The message and whether it's an error.
+    pub fn message(&self) -> Option<(&str, bool)> {
+        self.message
+            .as_ref()
+            .map(|(message, error)| (message.as_str(), *error))
+    }
+}
+
+// Something like "main.rs [+]" for the left of the status line and
+// "12:5  340 lines  LF  UTF-8" for the right.
+pub fn describe(buffer: &Buffer, name: &str) -> (String, String) {
+    let left = if buffer.is_modified() {
+        format!("{} [+]", name)
+    } else {
+        name.to_string()
+    };
+    let right = format!(
+        "{}:{}  {} lines  {}  {}",
+        buffer.cursor.y + 1,
+        buffer.cursor.col + 1,
+        buffer.line_count(),
+        buffer.line_ending.name(),
+        if buffer.bom { "UTF-8 BOM" } else { "UTF-8" },
+    );
+    (left, right)
+}
+
+// Pads the space between `left` and `right` so the whole thing is `width`
+// columns wide, or cuts `left` short when there isn't room for both.
+pub fn fit(left: &str, right: &str, width: usize) -> String {
+    let room = width.saturating_sub(display_width(right) + 1);
+    let mut line = String::new();
+    let mut used = 0;
+    for ch in left.chars() {
+        let w = display_width(ch.encode_utf8(&mut [0; 4]));
+        if used + w > room {
+            break;
+        }
+        line.push(ch);
+        used += w;
+    }
+    let gap = width.saturating_sub(used + display_width(right)).max(1);
+    line.extend(std::iter::repeat_n(' ', gap));
+    line.push_str(right);
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::LineEnding;
+
+    #[test]
+    fn test_status_line() {
+        let mut buffer = Buffer::new();
+        buffer.insert_text("one\ntwo\nthree");
+        buffer.move_up();
+        buffer.set_line_ending(LineEnding::CrLf);
+        let (left, right) = describe(&buffer, "notes.txt");
+        assert_eq!(left, "notes.txt [+]");
+        assert_eq!(right, "2:4  3 lines  CRLF  UTF-8");
+
+        assert_eq!(fit("a.rs", "1:1", 10), "a.rs   1:1");
+        assert_eq!(fit("long_name.rs", "1:1", 10), "long_n 1:1");
+        assert_eq!(fit("a.rs", "1:1", 2), " 1:1");
+    }
+
+    #[test]
+    fn test_status_errors_pile_up() {
+        let mut status = Status::default();
+        status.info("saved");
+        status.error("config.toml: width: too big\nfps: too small");
+        status.error("theme `nope` not found");
+        assert_eq!(
+            status.message(),
+            Some((
+                "config.toml: width: too big; fps: too small; theme `nope` not found",
+                true
+            ))
+        );
+        status.clear();
+        status.error("again");
+        assert_eq!(status.message(), Some(("again", true)));
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+// Hidden file right next to the one being edited, `.name.red-swp`.
+pub fn swap_path(filepath: &Path) -> PathBuf {
+    let name = filepath.file_name().unwrap_or_default().to_string_lossy();
+    filepath.with_file_name(format!(".{}.red-swp", name))
+}
+
+// Unsaved changes journaled by a session that never exited cleanly.
+#[derive(Debug)]
+pub struct Recovery {
+    pub swap_path: PathBuf,
+    pub contents: String,
+    pub summary: DiffSummary,
+}
+
+// Looks for a leftover swap file of `filepath`, which is only worth
+// recovering when it has something that isn't already `on_disk`.
+pub fn find_leftover(filepath: &Path, on_disk: &str) -> Option<Recovery> {
+    let swap_path = swap_path(filepath);
+    let contents = fs::read_to_string(&swap_path).ok()?;
+    if contents == on_disk {
+        let _ = fs::remove_file(&swap_path);
+        return None;
+    }
+    let summary = diff_summary(on_disk, &contents);
+    Some(Recovery {
+        swap_path,
+        contents,
+        summary,
+    })
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct DiffSummary {
+    pub added: usize,
+    pub removed: usize,
+    // 1-based, like editors show line numbers.
+    pub first_change: Option<usize>,
+}
+
+// Rough idea of what changed between `old` and `new`. Lines in common at the
+// start and end are skipped and whatever is left in between is compared
+// as a bag of lines, which is cheap and good enough to decide on a recovery.
+pub fn diff_summary(old: &str, new: &str) -> DiffSummary {
+    let old: Vec<_> = old.lines().collect();
+    let new: Vec<_> = new.lines().collect();
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old = &old[prefix..old.len() - suffix];
+    let new = &new[prefix..new.len() - suffix];
+
+    let mut counts: HashMap<&str, isize> = HashMap::new();
+    for line in old {
+        *counts.entry(line).or_default() -= 1;
+    }
+    for line in new {
+        *counts.entry(line).or_default() += 1;
+    }
+    let added = counts.values().filter(|&&n| n > 0).sum::<isize>() as usize;
+    let removed = -counts.values().filter(|&&n| n < 0).sum::<isize>() as usize;
+
+    DiffSummary {
+        added,
+        removed,
+        first_change: (!old.is_empty() || !new.is_empty()).then_some(prefix + 1),
+    }
+}
+
+impl fmt::Display for DiffSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "+{} -{} lines", self.added, self.removed)?;
+        if let Some(line) = self.first_change {
+            write!(f, ", first change at line {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_summary() {
+        assert_eq!(diff_summary("a\nb\n", "a\nb\n"), DiffSummary::default());
+        let summary = diff_summary("a\nb\nc\nd\n", "a\nB\nc\nd\ne\n");
+        assert_eq!(
+            summary,
+            DiffSummary {
+                added: 2,
+                removed: 1,
+                first_change: Some(2),
+            }
+        );
+        assert_eq!(summary.to_string(), "+2 -1 lines, first change at line 2");
+        // Moving lines around isn't adding or removing any.
+        let summary = diff_summary("a\nb\nc\n", "c\nb\na\n");
+        assert_eq!((summary.added, summary.removed), (0, 0));
+        assert_eq!(summary.first_change, Some(1));
+    }
+
+    #[test]
+    fn test_swap_path() {
+        assert_eq!(
+            swap_path(Path::new("src/main.rs")),
+            Path::new("src/.main.rs.red-swp")
+        );
+    }
+}
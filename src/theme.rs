@@ -0,0 +1,238 @@
+use std::path::Path;
+
+use crate::config::{self, Fields};
+use crate::highlight::TokenKind;
+use crate::toml::{self, Table};
+use crate::Color;
+
+// Besides the default one, which is "dark".
+const BUILTIN: &[(&str, &str)] = &[("light", include_str!("../themes/light.toml"))];
+
+// The color of everything that's drawn.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    pub name: String,
+    pub background: Color,
+    pub text: Color,
+    pub cursor: Color,
+    pub inactive_cursor: Color,
+    pub selection: Color,
+    pub search_match: Color,
+    pub separator: Color,
+    pub gutter: Color,
+    pub gutter_text: Color,
+    // The number of the line the cursor is on.
+    pub gutter_current: Color,
+    pub status_bar: Color,
+    pub status_text: Color,
+    pub error: Color,
+    // Syntax highlighting.
+    pub keyword: Color,
+    pub r#type: Color,
+    pub string: Color,
+    pub number: Color,
+    pub comment: Color,
+    pub heading: Color,
+    pub emphasis: Color,
+    pub variable: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            name: "dark".into(),
+            background: crate::BLACK,
+            text: crate::WHITE,
+            cursor: crate::WHITE,
+            inactive_cursor: crate::GRAY,
+            selection: crate::WHITE,
+            search_match: crate::YELLOW,
+            separator: crate::GRAY,
+            gutter: crate::BLACK,
+            gutter_text: crate::GRAY,
+            gutter_current: crate::WHITE,
+            status_bar: crate::GRAY,
+            status_text: crate::BLACK,
+            error: crate::RED,
+            keyword: crate::YELLOW,
+            r#type: crate::CYAN,
+            string: crate::GREEN,
+            number: crate::MAGENTA,
+            comment: crate::GRAY,
+            heading: crate::YELLOW,
+            emphasis: crate::CYAN,
+            variable: crate::ORANGE,
+        }
+    }
+}
+
+impl Theme {
+    // A theme file has a `#rrggbb` color for any of the fields,
+    // the ones it leaves out are the same as in the default theme.
+    pub fn parse(name: &str, text: &str) -> Result<Self, String> {
+        let table = toml::parse(text)?;
+        let mut theme = Theme {
+            name: name.to_string(),
+            ..Theme::default()
+        };
+        let mut errors = vec![];
+        theme.read(&mut Fields {
+            table: &table,
+            prefix: "",
+            errors: &mut errors,
+        });
+        if errors.is_empty() {
+            Ok(theme)
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+
+    // Takes whatever colors `fields` has.
+    pub fn read(&mut self, fields: &mut Fields) {
+        fields.color("background", &mut self.background);
+        fields.color("text", &mut self.text);
+        fields.color("cursor", &mut self.cursor);
+        fields.color("inactive_cursor", &mut self.inactive_cursor);
+        fields.color("selection", &mut self.selection);
+        fields.color("search_match", &mut self.search_match);
+        fields.color("separator", &mut self.separator);
+        fields.color("gutter", &mut self.gutter);
+        fields.color("gutter_text", &mut self.gutter_text);
+        fields.color("gutter_current", &mut self.gutter_current);
+        fields.color("status_bar", &mut self.status_bar);
+        fields.color("status_text", &mut self.status_text);
+        fields.color("error", &mut self.error);
+        fields.color("keyword", &mut self.keyword);
+        fields.color("type", &mut self.r#type);
+        fields.color("string", &mut self.string);
+        fields.color("number", &mut self.number);
+        fields.color("comment", &mut self.comment);
+        fields.color("heading", &mut self.heading);
+        fields.color("emphasis", &mut self.emphasis);
+        fields.color("variable", &mut self.variable);
+        fields.unknown(&[
+            "background",
+            "text",
+            "cursor",
+            "inactive_cursor",
+            "selection",
+            "search_match",
+            "separator",
+            "gutter",
+            "gutter_text",
+            "gutter_current",
+            "status_bar",
+            "status_text",
+            "error",
+            "keyword",
+            "type",
+            "string",
+            "number",
+            "comment",
+            "heading",
+            "emphasis",
+            "variable",
+        ]);
+    }
+
+    // With the colors in `overrides` instead, which were already checked.
+    pub fn with(&self, overrides: &Table) -> Self {
+        let mut theme = self.clone();
+        theme.read(&mut Fields {
+            table: overrides,
+            prefix: "",
+            errors: &mut vec![],
+        });
+        theme
+    }
+
+    pub fn token(&self, kind: TokenKind) -> Color {
+        match kind {
+            TokenKind::Keyword => self.keyword,
+            TokenKind::Type => self.r#type,
+            TokenKind::String => self.string,
+            TokenKind::Number => self.number,
+            TokenKind::Comment => self.comment,
+            TokenKind::Heading => self.heading,
+            TokenKind::Emphasis => self.emphasis,
+            TokenKind::Variable => self.variable,
+        }
+    }
+}
+
+// What `view.next_theme` goes through, the default theme first.
+pub struct Themes {
+    themes: Vec<Theme>,
+}
+
+impl Themes {
+    pub fn builtin() -> Self {
+        let builtin = BUILTIN.iter().map(|(name, text)| {
+            Theme::parse(name, text).unwrap_or_else(|err| panic!("{}.toml: {}", name, err))
+        });
+        let themes = std::iter::once(Theme::default()).chain(builtin).collect();
+        Self { themes }
+    }
+
+    // Themes in `dir` come after the built-in ones, unless they have
+    // the same name as one, which they then replace.
+    pub fn load_dir(&mut self, dir: &Path) -> Result<(), String> {
+        let (themes, errors) = config::load_toml_dir(dir, Theme::parse);
+        for theme in themes {
+            self.add(theme);
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("\n"))
+        }
+    }
+
+    pub fn add(&mut self, theme: Theme) {
+        match self.themes.iter_mut().find(|t| t.name == theme.name) {
+            Some(old) => *old = theme,
+            None => self.themes.push(theme),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Theme> {
+        self.themes.iter().find(|theme| theme.name == name)
+    }
+
+    // The one after `name`, going back to the first after the last.
+    pub fn next(&self, name: &str) -> &Theme {
+        let i = self.themes.iter().position(|theme| theme.name == name);
+        &self.themes[i.map_or(0, |i| (i + 1) % self.themes.len())]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_themes() {
+        let mut themes = Themes::builtin();
+        assert_eq!(themes.get("dark"), Some(&Theme::default()));
+        let light = themes.get("light").unwrap().clone();
+        assert_ne!(light.background, crate::BLACK);
+        assert_eq!(themes.next("dark").name, "light");
+        assert_eq!(themes.next("light").name, "dark");
+
+        let theme = Theme::parse("mine", "text = \"#ff0000\"").unwrap();
+        assert_eq!(theme.text, crate::Color::new(1.0, 0.0, 0.0, 1.0));
+        assert_eq!(theme.background, crate::BLACK);
+        themes.add(theme);
+        assert_eq!(themes.next("light").name, "mine");
+        assert_eq!(
+            Theme::parse("bad", "text = \"red\"\ntxet = \"#ff0000\"").unwrap_err(),
+            "text: `red` isn't a #rrggbb color\ntxet: unknown setting"
+        );
+
+        let overrides = toml::parse("keyword = \"#ffffff\"").unwrap();
+        let theme = light.with(&overrides);
+        assert_eq!(theme.keyword, crate::WHITE);
+        assert_eq!(theme.background, light.background);
+    }
+}
@@ -1,21 +1,31 @@
-use std::{ffi::c_void, mem::offset_of};
+use std::{ffi::c_void, mem::offset_of, ops::Range};
 
 use gl::types::{GLint, GLuint};
+use unicode_segmentation::UnicodeSegmentation;
 
 use crate::{
-    buffer::Buffer, gl_extra::GlAttrib, image::Image, v2, vector::Vector2, Color, BLACK, WHITE,
+    buffer::{display_width, Buffer, Cursor},
+    font::Font,
+    gl_extra::GlAttrib,
+    layout::Rect,
+    v2,
+    vector::Vector2,
+    Color,
 };
 
 #[repr(C)]
 pub struct TileGlyph {
     tile: Vector2<i32>,
-    ch: i32,
+    // Where the glyph is in the atlas and in its cell, see `font::Glyph`.
+    glyph_pos: Vector2<i32>,
+    glyph_size: Vector2<i32>,
+    glyph_offset: Vector2<i32>,
     fg_color: Color,
     bg_color: Color,
 }
 
 impl TileGlyph {
-    pub const fn gl_attributes() -> [GlAttrib; 4] {
+    pub const fn gl_attributes() -> [GlAttrib; 6] {
         let stride = size_of::<TileGlyph>() as gl::types::GLsizei;
         let normalized = gl::FALSE;
         [
@@ -28,10 +38,24 @@ impl TileGlyph {
             },
             GlAttrib {
                 r#type: gl::INT,
-                comps: 1,
+                comps: 2,
+                normalized,
+                stride,
+                offset: offset_of!(TileGlyph, glyph_pos),
+            },
+            GlAttrib {
+                r#type: gl::INT,
+                comps: 2,
                 normalized,
                 stride,
-                offset: offset_of!(TileGlyph, ch),
+                offset: offset_of!(TileGlyph, glyph_size),
+            },
+            GlAttrib {
+                r#type: gl::INT,
+                comps: 2,
+                normalized,
+                stride,
+                offset: offset_of!(TileGlyph, glyph_offset),
             },
             GlAttrib {
                 r#type: gl::FLOAT,
@@ -58,22 +82,32 @@ pub struct TileGlyphBuffer {
     pub resolution_uniform: GLint,
     pub camera_uniform: GLint,
     glyphs: Vec<TileGlyph>,
+    font: Font,
+    // Size of the atlas as the GPU has it, which changes when it grows.
+    texture_size: Vector2<i32>,
 }
 
-// // tmp
-// const FONT_SCALE: f32 = 5.0;
-const FONT_SCALE: f32 = 3.0;
-
 impl TileGlyphBuffer {
-    pub fn new() -> Self {
+    pub fn new(font: Font) -> Self {
         Self {
             time_uniform: -1,
             resolution_uniform: -1,
             camera_uniform: -1,
             glyphs: Vec::with_capacity(TILE_GLYPH_BUFF_CAP),
+            font,
+            texture_size: v2!(0, 0),
         }
     }
-    pub fn gl_init(&self) {
+
+    // How much screen a glyph takes.
+    pub fn cell_size(&self) -> Vector2<f32> {
+        v2!(
+            self.font.cell.x as f32 * self.font.scale,
+            self.font.cell.y as f32 * self.font.scale,
+        )
+    }
+
+    pub fn gl_init(&mut self) {
         unsafe {
             let mut vao: GLuint = 0;
             gl::GenVertexArrays(1, &mut vao);
@@ -122,15 +156,9 @@ impl TileGlyphBuffer {
                 gl::VertexAttribDivisor(index, 1);
             }
         }
-    }
-    pub fn load_texture_atlas(&self, file_path: &str) {
-        let mut font_texture = 0;
-        let Image {
-            ref mut pixels,
-            width,
-            height,
-        } = Image::load(file_path);
+
         unsafe {
+            let mut font_texture = 0;
             gl::ActiveTexture(gl::TEXTURE0);
             gl::GenTextures(1, &mut font_texture);
             gl::BindTexture(gl::TEXTURE_2D, font_texture);
@@ -141,17 +169,13 @@ impl TileGlyphBuffer {
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
             gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
 
-            gl::TexImage2D(
-                gl::TEXTURE_2D,
-                0,
-                gl::RGBA as i32,
-                width,
-                height,
-                0,
-                gl::RGBA,
-                gl::UNSIGNED_BYTE,
-                pixels.as_mut_ptr() as *mut c_void,
-            );
+            // Rows of coverage are one byte a pixel, not four.
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+
+            let mut max_size = 0;
+            gl::GetIntegerv(gl::MAX_TEXTURE_SIZE, &mut max_size);
+            let atlas = &mut self.font.atlas;
+            atlas.max_size = atlas.max_size.min(max_size);
         }
     }
     pub fn compile_shaders(&mut self, vert_shader: &str, frag_shader: &str) -> Result<(), String> {
@@ -175,7 +199,17 @@ impl TileGlyphBuffer {
             if scale_uniform == -1 {
                 eprintln!("scale uniform not found");
             }
-            gl::Uniform1f(scale_uniform, FONT_SCALE);
+            gl::Uniform1f(scale_uniform, self.font.scale);
+
+            let char_size_uniform = gl::GetUniformLocation(program, c"char_size".as_ptr());
+            if char_size_uniform == -1 {
+                eprintln!("char_size uniform not found");
+            }
+            gl::Uniform2f(
+                char_size_uniform,
+                self.font.cell.x as f32,
+                self.font.cell.y as f32,
+            );
 
             self.camera_uniform = gl::GetUniformLocation(program, c"camera".as_ptr());
             if self.camera_uniform == -1 {
@@ -192,10 +226,27 @@ impl TileGlyphBuffer {
         fg_color: Color,
         bg_color: Color,
     ) {
-        for (i, ch) in line.chars().enumerate() {
+        self.render_line_with(line, tile, |_| (fg_color, bg_color));
+    }
+
+    // Each glyph gets the colors `colors` picks for the byte offset it starts at.
+    pub fn render_line_with(
+        &mut self,
+        line: &str,
+        tile: Vector2<i32>,
+        colors: impl Fn(usize) -> (Color, Color),
+    ) {
+        // Combining marks and the like have no tile of their own,
+        // so each grapheme cluster is drawn with its first char.
+        for (i, (at, grapheme)) in line.grapheme_indices(true).enumerate() {
+            let ch = grapheme.chars().next().unwrap_or(' ');
+            let glyph = self.font.glyph(ch);
+            let (fg_color, bg_color) = colors(at);
             let tile_glyph = TileGlyph {
                 tile: tile + v2!(i as i32, 0),
-                ch: ch as i32,
+                glyph_pos: glyph.pos,
+                glyph_size: glyph.size,
+                glyph_offset: glyph.offset,
                 fg_color,
                 bg_color,
             };
@@ -203,16 +254,100 @@ impl TileGlyphBuffer {
         }
     }
 
-    pub fn gl_render_cursor(&mut self, buffer: &Buffer) {
+    // Like `render_line`, but the parts of `line` covered by `highlights`
+    // get their own colors. They must be sorted and not overlap.
+    pub fn render_highlighted_line(
+        &mut self,
+        line: &str,
+        tile: Vector2<i32>,
+        fg_color: Color,
+        bg_color: Color,
+        highlights: &[(Range<usize>, Color, Color)],
+    ) {
+        let mut x = 0;
+        let mut rest = 0;
+        for (range, hl_fg_color, hl_bg_color) in highlights {
+            for (text, fg_color, bg_color) in [
+                (&line[rest..range.start], fg_color, bg_color),
+                (&line[range.clone()], *hl_fg_color, *hl_bg_color),
+            ] {
+                self.render_line(text, tile + v2!(x as i32, 0), fg_color, bg_color);
+                x += display_width(text);
+            }
+            rest = range.end;
+        }
+        self.render_line(&line[rest..], tile + v2!(x as i32, 0), fg_color, bg_color);
+    }
+
+    pub fn gl_render_cursor(
+        &mut self,
+        buffer: &Buffer,
+        cursor: &Cursor,
+        fg_color: Color,
+        bg_color: Color,
+    ) {
         self.render_line(
-            &buffer.char_at_cursor().unwrap_or(' ').to_string(),
-            v2!(buffer.cursor.x as i32, -(buffer.cursor.y as i32)),
-            BLACK,
-            WHITE,
+            &buffer.grapheme_at(cursor).unwrap_or(" ".to_string()),
+            v2!(cursor.col as i32, -(cursor.y as i32)),
+            fg_color,
+            bg_color,
         );
     }
 
-    pub fn sync(&self) {
+    // Where the next `draw` goes: `rect` of a window `window_height` pixels
+    // tall, showing `resolution` worth of the world around `camera`.
+    pub fn gl_viewport(
+        &self,
+        rect: Rect,
+        window_height: i32,
+        resolution: Vector2<f32>,
+        camera: Vector2<f32>,
+    ) {
+        unsafe {
+            // GL counts from the bottom.
+            let y = window_height - rect.y - rect.h;
+            gl::Viewport(rect.x, y, rect.w, rect.h);
+            gl::Scissor(rect.x, y, rect.w, rect.h);
+            gl::Uniform2f(self.resolution_uniform, resolution.x, resolution.y);
+            gl::Uniform2f(self.camera_uniform, camera.x, camera.y);
+        }
+    }
+
+    // Also sends the glyphs that went in the atlas since the last time.
+    pub fn sync(&mut self) {
+        let atlas = &mut self.font.atlas;
+        if atlas.dirty {
+            unsafe {
+                let pixels = atlas.pixels.as_ptr() as *const c_void;
+                if self.texture_size == atlas.size {
+                    gl::TexSubImage2D(
+                        gl::TEXTURE_2D,
+                        0,
+                        0,
+                        0,
+                        atlas.size.x,
+                        atlas.size.y,
+                        gl::RED,
+                        gl::UNSIGNED_BYTE,
+                        pixels,
+                    );
+                } else {
+                    gl::TexImage2D(
+                        gl::TEXTURE_2D,
+                        0,
+                        gl::R8 as i32,
+                        atlas.size.x,
+                        atlas.size.y,
+                        0,
+                        gl::RED,
+                        gl::UNSIGNED_BYTE,
+                        pixels,
+                    );
+                    self.texture_size = atlas.size;
+                }
+            }
+            atlas.dirty = false;
+        }
         unsafe {
             gl::BufferSubData(
                 gl::ARRAY_BUFFER,
@@ -0,0 +1,526 @@
+use std::collections::{BTreeMap, HashSet};
+use std::iter::{self, Peekable};
+use std::str::Chars;
+
+// All of TOML 1.0 but dates and times, which config files don't need.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<Value>),
+    Table(Table),
+}
+
+pub type Table = BTreeMap<String, Value>;
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "a string",
+            Value::Integer(_) => "an integer",
+            Value::Float(_) => "a float",
+            Value::Boolean(_) => "a boolean",
+            Value::Array(_) => "an array",
+            Value::Table(_) => "a table",
+        }
+    }
+}
+
+pub fn parse(text: &str) -> Result<Table, String> {
+    let mut parser = Parser {
+        chars: text.chars().peekable(),
+        line: 1,
+    };
+    parser
+        .document()
+        .map_err(|err| format!("line {}: {}", parser.line, err))
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    line: usize,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<char> {
+        let ch = self.chars.next();
+        if ch == Some('\n') {
+            self.line += 1;
+        }
+        ch
+    }
+
+    fn eat(&mut self, ch: char) -> bool {
+        if self.chars.peek() == Some(&ch) {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, ch: char) -> Result<(), String> {
+        if self.eat(ch) {
+            Ok(())
+        } else {
+            Err(format!("expected `{}`", ch))
+        }
+    }
+
+    // Spaces and tabs, and with `newlines` comments and line breaks too.
+    fn skip_blank(&mut self, newlines: bool) {
+        while let Some(&ch) = self.chars.peek() {
+            match ch {
+                ' ' | '\t' | '\r' => {}
+                '\n' if newlines => {}
+                '#' if newlines => {
+                    while self.chars.peek().is_some_and(|&ch| ch != '\n') {
+                        self.next();
+                    }
+                    continue;
+                }
+                _ => break,
+            }
+            self.next();
+        }
+    }
+
+    // Whatever is left of a line after a header or a value.
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_blank(false);
+        match self.chars.peek() {
+            None | Some('\n') | Some('#') => Ok(()),
+            Some(ch) => Err(format!("unexpected `{}`", ch)),
+        }
+    }
+
+    fn document(&mut self) -> Result<Table, String> {
+        let mut root = Table::new();
+        let mut path: Vec<String> = vec![];
+        // Tables that can't get a header anymore, because they already had
+        // one or dotted keys made them. The ones only made on the way to
+        // another table, like `a` by `[a.b]`, still can.
+        let mut defined: HashSet<Vec<String>> = HashSet::new();
+        // Tables and arrays written out as values, which are whole as they are.
+        let mut inline: HashSet<Vec<String>> = HashSet::new();
+        loop {
+            self.skip_blank(true);
+            match self.chars.peek() {
+                None => return Ok(root),
+                Some('[') => {
+                    self.next();
+                    let array = self.eat('[');
+                    path = self.keys()?;
+                    self.expect(']')?;
+                    if array {
+                        self.expect(']')?;
+                    }
+                    self.end_of_line()?;
+                    not_inline(&inline, &path)?;
+                    let (last, parents) = path.split_last().unwrap();
+                    let table = table_at(&mut root, parents)?;
+                    if array {
+                        match table
+                            .entry(last.clone())
+                            .or_insert_with(|| Value::Array(vec![]))
+                        {
+                            Value::Array(tables) => tables.push(Value::Table(Table::new())),
+                            _ => return Err(format!("`{}` isn't an array of tables", last)),
+                        }
+                        // What the last one of them had doesn't matter to the new one.
+                        let under = |p: &Vec<String>| p.len() > path.len() && p.starts_with(&path);
+                        defined.retain(|p| !under(p));
+                        inline.retain(|p| !under(p));
+                    } else {
+                        let value = table
+                            .entry(last.clone())
+                            .or_insert_with(|| Value::Table(Table::new()));
+                        if !matches!(value, Value::Table(_)) || !defined.insert(path.clone()) {
+                            return Err(format!("`{}` is defined twice", path.join(".")));
+                        }
+                    }
+                }
+                Some(_) => {
+                    let keys = self.keys()?;
+                    self.expect('=')?;
+                    self.skip_blank(false);
+                    let value = self.value()?;
+                    self.end_of_line()?;
+                    let full = [&path[..], &keys[..]].concat();
+                    not_inline(&inline, &full[..full.len() - 1])?;
+                    if matches!(value, Value::Table(_) | Value::Array(_)) {
+                        inline.insert(full);
+                    }
+                    insert(table_at(&mut root, &path)?, &keys, value)?;
+                    for i in 1..keys.len() {
+                        defined.insert([&path[..], &keys[..i]].concat());
+                    }
+                }
+            }
+        }
+    }
+
+    // A key, or a dotted one like `a.b`, with whatever blanks are around.
+    fn keys(&mut self) -> Result<Vec<String>, String> {
+        let mut keys = vec![];
+        loop {
+            self.skip_blank(false);
+            keys.push(self.key()?);
+            self.skip_blank(false);
+            if !self.eat('.') {
+                return Ok(keys);
+            }
+        }
+    }
+
+    fn key(&mut self) -> Result<String, String> {
+        match self.chars.peek() {
+            Some('"') | Some('\'') => self.string(),
+            _ => {
+                let mut key = String::new();
+                while let Some(&ch) = self
+                    .chars
+                    .peek()
+                    .filter(|ch| ch.is_ascii_alphanumeric() || **ch == '_' || **ch == '-')
+                {
+                    key.push(ch);
+                    self.next();
+                }
+                if key.is_empty() {
+                    return Err("expected a key".into());
+                }
+                Ok(key)
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.chars.peek() {
+            Some('"') | Some('\'') => self.string().map(Value::String),
+            Some('{') => {
+                self.next();
+                let mut table = Table::new();
+                let mut inline = HashSet::new();
+                loop {
+                    self.skip_blank(false);
+                    if self.eat('}') {
+                        return Ok(Value::Table(table));
+                    }
+                    let keys = self.keys()?;
+                    self.expect('=')?;
+                    self.skip_blank(false);
+                    let value = self.value()?;
+                    not_inline(&inline, &keys[..keys.len() - 1])?;
+                    if matches!(value, Value::Table(_)) {
+                        inline.insert(keys.clone());
+                    }
+                    insert(&mut table, &keys, value)?;
+                    self.skip_blank(false);
+                    if !self.eat(',') {
+                        self.expect('}')?;
+                        return Ok(Value::Table(table));
+                    }
+                }
+            }
+            Some('[') => {
+                self.next();
+                let mut values = vec![];
+                loop {
+                    self.skip_blank(true);
+                    if self.eat(']') {
+                        return Ok(Value::Array(values));
+                    }
+                    values.push(self.value()?);
+                    self.skip_blank(true);
+                    if !self.eat(',') {
+                        self.expect(']')?;
+                        return Ok(Value::Array(values));
+                    }
+                }
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&ch) = self
+                    .chars
+                    .peek()
+                    .filter(|ch| ch.is_ascii_alphanumeric() || "+-._".contains(**ch))
+                {
+                    word.push(ch);
+                    self.next();
+                }
+                match word.as_str() {
+                    "true" => return Ok(Value::Boolean(true)),
+                    "false" => return Ok(Value::Boolean(false)),
+                    "" => return Err("expected a value".into()),
+                    _ => {}
+                }
+                let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+                let date = word.len() > 4 && digits(&word[..4]) && word[4..].starts_with('-');
+                let time = digits(&word) && self.chars.peek() == Some(&':');
+                if date || time {
+                    return Err("dates and times aren't supported".into());
+                }
+                let number = word.replace('_', "");
+                let radix = match number.get(..2) {
+                    Some("0x") => 16,
+                    Some("0o") => 8,
+                    Some("0b") => 2,
+                    _ => 10,
+                };
+                if radix != 10 {
+                    i64::from_str_radix(&number[2..], radix)
+                        .map(Value::Integer)
+                        .map_err(|_| format!("`{}` isn't a value", word))
+                } else if let Ok(n) = number.parse() {
+                    Ok(Value::Integer(n))
+                } else if matches!(number.trim_start_matches(['+', '-']), "inf" | "nan") {
+                    Ok(Value::Float(number.parse().unwrap()))
+                } else if let Some(n) = number
+                    .parse()
+                    .ok()
+                    .filter(|_| number.ends_with(|ch: char| ch.is_ascii_digit()))
+                {
+                    Ok(Value::Float(n))
+                } else {
+                    Err(format!("`{}` isn't a value", word))
+                }
+            }
+        }
+    }
+
+    // Basic strings with escapes, or literal ones in single quotes, either
+    // kind going on for several lines between three quotes.
+    fn string(&mut self) -> Result<String, String> {
+        let quote = self.next().unwrap();
+        let multiline = self.eat(quote);
+        if multiline && !self.eat(quote) {
+            return Ok(String::new());
+        }
+        if multiline {
+            // Not a line break that's right after the quotes.
+            self.eat('\r');
+            self.eat('\n');
+        }
+        let mut s = String::new();
+        loop {
+            match self.next() {
+                None => return Err("unterminated string".into()),
+                Some('\n') if !multiline => return Err("unterminated string".into()),
+                Some(ch) if ch == quote && !multiline => return Ok(s),
+                Some(ch) if ch == quote => {
+                    let mut quotes = 1;
+                    while quotes < 5 && self.eat(quote) {
+                        quotes += 1;
+                    }
+                    // Up to two of them can go right before the closing three.
+                    if quotes >= 3 {
+                        s.extend(iter::repeat_n(quote, quotes - 3));
+                        return Ok(s);
+                    }
+                    s.extend(iter::repeat_n(quote, quotes));
+                }
+                Some('\\') if quote == '"' => s.extend(self.escape(multiline)?),
+                Some(ch) => s.push(ch),
+            }
+        }
+    }
+
+    // What a backslash stands for. At the end of a line of a multi-line
+    // string it's nothing, and takes the blanks up to the next text with it.
+    fn escape(&mut self, multiline: bool) -> Result<Option<char>, String> {
+        let ch = match self.next() {
+            Some('b') => '\u{8}',
+            Some('t') => '\t',
+            Some('n') => '\n',
+            Some('f') => '\u{c}',
+            Some('r') => '\r',
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some(u @ ('u' | 'U')) => {
+                let len = if u == 'u' { 4 } else { 8 };
+                let hex: String = (0..len).filter_map(|_| self.next()).collect();
+                u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| format!("bad unicode escape `\\{}{}`", u, hex))?
+            }
+            Some(ch @ (' ' | '\t' | '\r' | '\n')) if multiline => {
+                let mut newline = ch == '\n';
+                while let Some(&ch) = self.chars.peek().filter(|ch| ch.is_whitespace()) {
+                    newline |= ch == '\n';
+                    self.next();
+                }
+                if !newline {
+                    return Err("unknown escape `\\ `".into());
+                }
+                return Ok(None);
+            }
+            Some(ch) => return Err(format!("unknown escape `\\{}`", ch)),
+            None => return Err("unterminated string".into()),
+        };
+        Ok(Some(ch))
+    }
+}
+
+// Errors if `path` goes into a table that was written out whole.
+fn not_inline(inline: &HashSet<Vec<String>>, path: &[String]) -> Result<(), String> {
+    match (1..=path.len()).find(|&i| inline.contains(&path[..i])) {
+        Some(i) => Err(format!("`{}` was defined inline", path[..i].join("."))),
+        None => Ok(()),
+    }
+}
+
+// Puts `value` at `keys` in `table`, with the tables in between made as needed.
+fn insert(table: &mut Table, keys: &[String], value: Value) -> Result<(), String> {
+    let (last, parents) = keys.split_last().unwrap();
+    let table = table_at(table, parents)?;
+    if table.contains_key(last) {
+        return Err(format!("`{}` is defined twice", keys.join(".")));
+    }
+    table.insert(last.clone(), value);
+    Ok(())
+}
+
+// The table `path` leads to, where the last of an array of tables stands
+// for the whole array.
+fn table_at<'a>(root: &'a mut Table, path: &[String]) -> Result<&'a mut Table, String> {
+    let mut table = root;
+    for (i, key) in path.iter().enumerate() {
+        let value = table
+            .entry(key.clone())
+            .or_insert_with(|| Value::Table(Table::new()));
+        table = match value {
+            Value::Table(table) => table,
+            Value::Array(values) => match values.last_mut() {
+                Some(Value::Table(table)) => table,
+                _ => return Err(format!("`{}` isn't a table", path[..=i].join("."))),
+            },
+            _ => return Err(format!("`{}` isn't a table", path[..=i].join("."))),
+        };
+    }
+    Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toml() {
+        let table = parse(
+            r#"
+            # comment
+            name = "red" # another one
+            'literal' = 'C:\path'
+            [font]
+            scale = 2.5
+            size = 1_024
+            bold = false
+            [colors.dark]
+            list = [
+                "a\tb", "\u00e9",  # trailing comma
+            ]
+            [[grammar]]
+            name = "rust"
+            [[grammar]]
+            name = "c"
+            "#,
+        )
+        .unwrap();
+        let s = |s: &str| Value::String(s.into());
+        assert_eq!(table["name"], s("red"));
+        assert_eq!(table["literal"], s("C:\\path"));
+        let Value::Table(font) = &table["font"] else {
+            panic!()
+        };
+        assert_eq!(font["scale"], Value::Float(2.5));
+        assert_eq!(font["size"], Value::Integer(1024));
+        assert_eq!(font["bold"], Value::Boolean(false));
+        let Value::Table(colors) = &table["colors"] else {
+            panic!()
+        };
+        let Value::Table(dark) = &colors["dark"] else {
+            panic!()
+        };
+        assert_eq!(dark["list"], Value::Array(vec![s("a\tb"), s("é")]));
+        let Value::Array(grammars) = &table["grammar"] else {
+            panic!()
+        };
+        assert_eq!(grammars.len(), 2);
+        assert_eq!(
+            grammars[1],
+            Value::Table(Table::from([("name".into(), s("c"))]))
+        );
+
+        assert_eq!(
+            parse("a = 1\na = 2").unwrap_err(),
+            "line 2: `a` is defined twice"
+        );
+        assert_eq!(parse("a = \"b").unwrap_err(), "line 1: unterminated string");
+        assert_eq!(parse("\n\na = 1 2").unwrap_err(), "line 3: unexpected `2`");
+        assert_eq!(
+            parse("a = nope").unwrap_err(),
+            "line 1: `nope` isn't a value"
+        );
+        assert!(parse("a = 1\n[a]").is_err());
+        assert!(parse("[a\nb = 1").is_err());
+    }
+
+    #[test]
+    fn test_toml_tables_and_strings() {
+        let table = parse(
+            r#"
+            [a.b]
+            c = 1
+            [a]
+            d.e = { f = 0x1f, "g" = [-inf] }
+            s = """
+  one \
+    two\b\f\U0001F600 ""three"""""
+            l = '''\n'''
+            "#,
+        )
+        .unwrap();
+        let Value::Table(a) = &table["a"] else {
+            panic!()
+        };
+        assert_eq!(
+            a["b"],
+            Value::Table(Table::from([("c".into(), Value::Integer(1))]))
+        );
+        let Value::Table(d) = &a["d"] else { panic!() };
+        let Value::Table(e) = &d["e"] else { panic!() };
+        assert_eq!(e["f"], Value::Integer(31));
+        assert_eq!(e["g"], Value::Array(vec![Value::Float(f64::NEG_INFINITY)]));
+        assert_eq!(
+            a["s"],
+            Value::String("  one two\u{8}\u{c}😀 \"\"three\"\"".into())
+        );
+        assert_eq!(a["l"], Value::String("\\n".into()));
+        assert!(matches!(parse("n = nan").unwrap()["n"], Value::Float(n) if n.is_nan()));
+
+        assert_eq!(
+            parse("[a]\n[a]").unwrap_err(),
+            "line 2: `a` is defined twice"
+        );
+        assert!(parse("[a]\nb.c = 1\n[a.b]").is_err());
+        assert!(parse("a = { b = 1, b = 2 }").is_err());
+        assert!(parse("a = { b = { c = 1 }, b.d = 2 }").is_err());
+        assert_eq!(
+            parse("a = { x = 1 }\n[a]").unwrap_err(),
+            "line 2: `a` was defined inline"
+        );
+        assert!(parse("a = { x = 1 }\na.y = 2").is_err());
+        assert!(parse("a = { x = 1 }\n[a.b]").is_err());
+        assert!(parse("a = [{ x = 1 }]\n[[a]]").is_err());
+        assert!(parse("[a]\nb = { x = 1 }\n[a.b.c]").is_err());
+        assert_eq!(
+            parse("a = 1979-05-27").unwrap_err(),
+            "line 1: dates and times aren't supported"
+        );
+        // Each table in an array gets its own subtables.
+        assert!(parse("[[a]]\n[a.b]\n[[a]]\n[a.b]").is_ok());
+    }
+}
@@ -1,4 +1,4 @@
-#[derive(Debug, Default, Copy, Clone)]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
 #[repr(C)]
 pub struct Vector2<T> {
     pub x: T,
@@ -98,7 +98,7 @@ impl<T: ops::Div<Output = T> + Copy> ops::DivAssign<Vector2<T>> for Vector2<T> {
     }
 }
 
-#[derive(Debug, Default, Copy, Clone)]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
 #[repr(C)]
 pub struct Vector4<T> {
     pub x: T,
@@ -0,0 +1,171 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::Hasher;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Instant;
+use std::time::SystemTime;
+
+// How often the file gets looked at when there's no inotify.
+const POLL_INTERVAL_MS: u128 = 1000;
+
+// What a file looked like when it was last read or written,
+// to tell whether someone else changed it since.
+#[derive(Debug, Clone)]
+pub struct Fingerprint {
+    mtime: Option<SystemTime>,
+    len: u64,
+    hash: u64,
+}
+
+impl Fingerprint {
+    pub fn new(contents: &[u8], mtime: Option<SystemTime>) -> Self {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(contents);
+        Self {
+            mtime,
+            len: contents.len() as u64,
+            hash: hasher.finish(),
+        }
+    }
+
+    // Only reads the file when its metadata doesn't match `previous`.
+    pub fn of(path: &Path, previous: Option<&Fingerprint>) -> io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        let mtime = metadata.modified().ok();
+        if let Some(previous) = previous {
+            if mtime.is_some() && previous.mtime == mtime && previous.len == metadata.len() {
+                return Ok(previous.clone());
+            }
+        }
+        Ok(Self::new(&fs::read(path)?, mtime))
+    }
+
+    // A touched file is still the same file.
+    pub fn same_contents(&self, other: &Fingerprint) -> bool {
+        self.len == other.len && self.hash == other.hash
+    }
+}
+
+// Tells when a file might have changed, through inotify when it can,
+// otherwise by saying so every now and then.
+pub struct Watcher {
+    path: PathBuf,
+    #[cfg(target_os = "linux")]
+    inotify: Option<inotify::Inotify>,
+    last_poll: Instant,
+}
+
+impl Watcher {
+    pub fn new(path: &Path) -> Self {
+        // Saving atomically replaces the file, so it's its directory that gets watched.
+        #[cfg(target_os = "linux")]
+        let inotify = {
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            inotify::Inotify::new(dir).ok()
+        };
+        Self {
+            path: path.to_owned(),
+            #[cfg(target_os = "linux")]
+            inotify,
+            last_poll: Instant::now(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn poll(&mut self) -> bool {
+        #[cfg(target_os = "linux")]
+        if let Some(inotify) = &self.inotify {
+            return inotify.drain();
+        }
+        if self.last_poll.elapsed().as_millis() < POLL_INTERVAL_MS {
+            return false;
+        }
+        self.last_poll = Instant::now();
+        true
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::fd::AsRawFd;
+    use std::os::fd::FromRawFd;
+    use std::os::fd::OwnedFd;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    pub struct Inotify {
+        fd: OwnedFd,
+    }
+
+    impl Inotify {
+        pub fn new(dir: &Path) -> io::Result<Self> {
+            let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+            let dir = CString::new(dir.as_os_str().as_bytes())?;
+            let mask = libc::IN_CLOSE_WRITE
+                | libc::IN_MODIFY
+                | libc::IN_CREATE
+                | libc::IN_DELETE
+                | libc::IN_MOVED_TO;
+            if unsafe { libc::inotify_add_watch(fd.as_raw_fd(), dir.as_ptr(), mask) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Self { fd })
+        }
+
+        // Whether anything happened in the directory since the last call.
+        pub fn drain(&self) -> bool {
+            let mut events = [0u8; 4096];
+            let mut any = false;
+            loop {
+                let n = unsafe {
+                    libc::read(
+                        self.fd.as_raw_fd(),
+                        events.as_mut_ptr() as *mut libc::c_void,
+                        events.len(),
+                    )
+                };
+                if n <= 0 {
+                    return any;
+                }
+                any = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_dir::TestDir;
+
+    #[test]
+    fn test_fingerprint() {
+        let dir = TestDir::new("fingerprint");
+        let path = dir.join("file");
+        fs::write(&path, "one").unwrap();
+        let first = Fingerprint::of(&path, None).unwrap();
+        assert!(first.same_contents(&Fingerprint::new(b"one", None)));
+        assert!(Fingerprint::of(&path, Some(&first))
+            .unwrap()
+            .same_contents(&first));
+
+        fs::write(&path, "two!").unwrap();
+        assert!(!Fingerprint::of(&path, Some(&first))
+            .unwrap()
+            .same_contents(&first));
+    }
+}